@@ -35,18 +35,18 @@ impl Camera {
         let lower_left_corner = origin - horizontal/2.0 - vertical/2.0 - focus_disc*w;
         let lens_radius = aperture / 2.0;
 
-        Camera {origin: origin,
-        horizontal: horizontal,
-        vertical: vertical,
-        lower_left_corner: lower_left_corner,
-        u: u, v: v,
-        lens_radius: lens_radius,
-        focus_disc: focus_disc,
-        aperture: aperture,
-        lookat: lookat,
-        vup: vup,
-        vfov: vfov,
-        aspect_ratio: aspect_ratio}
+        Camera {origin,
+        horizontal,
+        vertical,
+        lower_left_corner,
+        u, v,
+        lens_radius,
+        focus_disc,
+        aperture,
+        lookat,
+        vup,
+        vfov,
+        aspect_ratio}
     }
 
     pub fn set_position(&mut self, lookfrom: Vec3) {
@@ -74,6 +74,11 @@ impl Camera {
         Ray::new(self.origin+offset, self.lower_left_corner + s*self.horizontal + t*self.vertical - self.origin - offset)
     }
 
+    // Ray through the center of the lens, sharp at any distance
+    pub fn get_pinhole_ray(&self, s: f64, t: f64) -> Ray {
+        Ray::new(self.origin, self.lower_left_corner + s*self.horizontal + t*self.vertical - self.origin)
+    }
+
 
 
 }
@@ -14,7 +14,7 @@ pub struct Color {
 impl Color {
     // Constructor
     pub fn new(r: f64, g: f64, b: f64) -> Self {
-        Color{r: r, g: g, b: b}
+        Color{r, g, b}
     }
 
     // Accessors
@@ -28,6 +28,11 @@ impl Color {
         self.b
     }
 
+    // Operations
+    pub fn max_component(&self) -> f64 {
+        f64::max(self.r, f64::max(self.g, self.b))
+    }
+
 }
 
 impl Display for Color {
@@ -0,0 +1,163 @@
+use std::f64::consts::PI;
+use crate::vec3::Vec3;
+use crate::color::Color;
+
+// Light coming from infinitely far away, out of an equirectangular HDR or EXR image
+// The top row is straight up, u goes around the vertical axis from +X towards +Z.
+// Directions are sampled proportionally to the luminance of the pixels, so small
+// and bright suns are found by far more than the few bounces hitting them.
+pub struct Environment {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    // Around the vertical axis, in radians
+    rotation: f64,
+    intensity: f64,
+    // Cumulative distributions of the pixels' luminance times the sine of their
+    // elevation, over the rows then over the columns of every row
+    rows_cdf: Vec<f64>,
+    columns_cdf: Vec<f64>,
+}
+
+fn luminance(c: Color) -> f64 {
+    0.2126 * c.r + 0.7152 * c.g + 0.0722 * c.b
+}
+
+// Normalized cumulative distribution of weights, starting with 0, and their sum
+fn cdf(weights: impl Iterator<Item = f64>) -> (Vec<f64>, f64) {
+    let mut cdf = vec![0.0];
+    for w in weights {
+        cdf.push(cdf[cdf.len() - 1] + w);
+    }
+    let total = cdf[cdf.len() - 1];
+    if total > 0.0 {
+        cdf.iter_mut().for_each(|c| *c /= total);
+    }
+    (cdf, total)
+}
+
+// Bin of a cdf a sample falls in, and where in it
+fn sample_cdf(cdf: &[f64], u: f64) -> (usize, f64) {
+    let i = usize::min(cdf.partition_point(|&c| c <= u).saturating_sub(1), cdf.len() - 2);
+    let width = cdf[i + 1] - cdf[i];
+    let offset = if width > 0.0 { (u - cdf[i]) / width } else { 0.5 };
+    (i, f64::clamp(offset, 0.0, 1.0))
+}
+
+impl Environment {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>, rotation: f64, intensity: f64) -> Self {
+        assert!(width > 0 && height > 0 && pixels.len() == width * height, "Environment size does not match its pixels");
+        let sin_theta = |y: usize| f64::sin(PI * (y as f64 + 0.5) / height as f64);
+
+        let mut columns_cdf = Vec::with_capacity(height * (width + 1));
+        let mut row_weights = Vec::with_capacity(height);
+        for y in 0..height {
+            let (cdf, total) = cdf(pixels[y * width..(y + 1) * width].iter().map(|&c| luminance(c) * sin_theta(y)));
+            columns_cdf.extend(cdf);
+            row_weights.push(total);
+        }
+        let (rows_cdf, _) = cdf(row_weights.into_iter());
+
+        Environment{width, height, pixels, rotation: rotation.to_radians(), intensity, rows_cdf, columns_cdf}
+    }
+
+    // Load an HDR, EXR or any other image, rotation in degrees
+    pub fn load(filename: &str, rotation: f64, intensity: f64) -> Result<Self, image::ImageError> {
+        let img = image::open(filename)?.into_rgb32f();
+        let pixels = img.pixels().map(|p| Color::new(p.0[0] as f64, p.0[1] as f64, p.0[2] as f64)).collect();
+        Ok(Environment::new(img.width() as usize, img.height() as usize, pixels, rotation, intensity))
+    }
+
+    // Image coordinates in [0,1] of a direction
+    fn uv(&self, direction: Vec3) -> (f64, f64) {
+        let d = direction.unit();
+        let theta = f64::acos(f64::clamp(d.y(), -1.0, 1.0));
+        let phi = (f64::atan2(d.z(), d.x()) - self.rotation).rem_euclid(2.0 * PI);
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    fn pixel(&self, u: f64, v: f64) -> (usize, usize) {
+        let x = usize::min((u * self.width as f64) as usize, self.width - 1);
+        let y = usize::min((v * self.height as f64) as usize, self.height - 1);
+        (x, y)
+    }
+
+    // Radiance coming from a direction
+    pub fn value(&self, direction: Vec3) -> Color {
+        let (u, v) = self.uv(direction);
+        let (x, y) = self.pixel(u, v);
+        self.pixels[x + y * self.width] * self.intensity
+    }
+
+    // Direction towards the environment, and its density over solid angles
+    pub fn sample(&self, u1: f64, u2: f64) -> (Vec3, f64) {
+        let (y, dy) = sample_cdf(&self.rows_cdf, u1);
+        let (x, dx) = sample_cdf(&self.columns_cdf[y * (self.width + 1)..(y + 1) * (self.width + 1)], u2);
+        let u = (x as f64 + dx) / self.width as f64;
+        let v = (y as f64 + dy) / self.height as f64;
+
+        let (theta, phi) = (v * PI, u * 2.0 * PI + self.rotation);
+        let direction = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+        (direction, self.pdf(direction))
+    }
+
+    // Density over solid angles of sample
+    pub fn pdf(&self, direction: Vec3) -> f64 {
+        let (u, v) = self.uv(direction);
+        let sin_theta = f64::sin(v * PI);
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        let (x, y) = self.pixel(u, v);
+        let row = &self.columns_cdf[y * (self.width + 1)..(y + 1) * (self.width + 1)];
+        let p = (self.rows_cdf[y + 1] - self.rows_cdf[y]) * (row[x + 1] - row[x]);
+        // From the image's unit square, whose pixels are 1/(width*height) in area
+        p * (self.width * self.height) as f64 / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Dim sky with a small bright sun and a black row
+    fn sky(rotation: f64) -> Environment {
+        let (width, height) = (8, 4);
+        let pixels = (0..width * height).map(|i| match i {
+            10 => Color::new(50.0, 45.0, 40.0),
+            24..=31 => Color::new(0.0, 0.0, 0.0),
+            _ => Color::new(0.2 + 0.1 * (i % 3) as f64, 0.3, 0.5),
+        }).collect();
+        Environment::new(width, height, pixels, rotation, 1.0)
+    }
+
+    #[test]
+    fn pdf_integrates_to_one() {
+        let env = sky(0.0);
+        let (n_theta, n_phi) = (400, 800);
+        let (d_theta, d_phi) = (PI / n_theta as f64, 2.0 * PI / n_phi as f64);
+        let mut integral = 0.0;
+        for i in 0..n_theta {
+            let theta = (i as f64 + 0.5) * d_theta;
+            for j in 0..n_phi {
+                let phi = (j as f64 + 0.5) * d_phi;
+                let direction = Vec3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin());
+                integral += env.pdf(direction) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        assert!((integral - 1.0).abs() < 1e-3, "{}", integral);
+    }
+
+    #[test]
+    fn sample_pdf_matches() {
+        fastrand::seed(3);
+        let env = sky(30.0);
+        for _ in 0..1000 {
+            let (direction, pdf) = env.sample(fastrand::f64(), fastrand::f64());
+            assert!(pdf > 0.0);
+            assert!((pdf - env.pdf(direction)).abs() <= 1e-9 * pdf, "{} against {}", pdf, env.pdf(direction));
+            // Nothing comes from the black row at the bottom
+            assert!(luminance(env.value(direction)) > 0.0);
+        }
+    }
+}
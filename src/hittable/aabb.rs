@@ -0,0 +1,64 @@
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+
+// Axis aligned bounding box, used to skip whole groups of primitives
+#[derive(Debug, Copy, Clone)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Aabb{min, max}
+    }
+
+    pub fn empty() -> Self {
+        Aabb{min: Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+             max: Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY)}
+    }
+
+    // Smallest box containing both
+    pub fn union(&self, other: Aabb) -> Aabb {
+        Aabb{min: Vec3::new(f64::min(self.min.x(), other.min.x()), f64::min(self.min.y(), other.min.y()), f64::min(self.min.z(), other.min.z())),
+             max: Vec3::new(f64::max(self.max.x(), other.max.x()), f64::max(self.max.y(), other.max.y()), f64::max(self.max.z(), other.max.z()))}
+    }
+
+    pub fn grow(&self, p: Vec3) -> Aabb {
+        self.union(Aabb::new(p, p))
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    // Slab test, returns the parametric range of the ray inside the box
+    pub fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let o = [r.origin().x(), r.origin().y(), r.origin().z()];
+        let d = [r.direction().x(), r.direction().y(), r.direction().z()];
+        let min = [self.min.x(), self.min.y(), self.min.z()];
+        let max = [self.max.x(), self.max.y(), self.max.z()];
+
+        let mut t0 = t_min;
+        let mut t1 = t_max;
+        for a in 0..3 {
+            let inv_d = 1.0 / d[a];
+            let mut near = (min[a] - o[a]) * inv_d;
+            let mut far = (max[a] - o[a]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut near, &mut far);
+            }
+            // NaN from 0 * inf on a slab boundary is ignored by max/min
+            t0 = f64::max(near, t0);
+            t1 = f64::min(far, t1);
+            if t1 < t0 {
+                return None;
+            }
+        }
+        Some((t0, t1))
+    }
+}
@@ -0,0 +1,180 @@
+use crate::ray::Ray;
+use crate::hittable::{Hittable, HitRecord};
+
+// Constructive solid geometry over closed Hittables
+// Operands are queried repeatedly along the ray to get their full list of
+// entry and exit points, which are then merged according to the operation.
+// A CSG node is itself closed, so nodes can be nested.
+// Cutout materials are ignored on the operands, whose boundaries must all count,
+// the top level World only tests the opacity of the resulting surface.
+
+const CSG_EPS: f64 = 1e-6;      // Step past a boundary before looking for the next one
+const MAX_BOUNDARIES: usize = 64;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    Difference,
+}
+
+impl CsgOp {
+    fn inside(&self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOp::Union        => in_a || in_b,
+            CsgOp::Intersection => in_a && in_b,
+            CsgOp::Difference   => in_a && !in_b,
+        }
+    }
+}
+
+pub struct Csg {
+    op: CsgOp,
+    a: Box<dyn Hittable>,
+    b: Box<dyn Hittable>,
+}
+
+impl Csg {
+    pub fn new(op: CsgOp, a: Box<dyn Hittable>, b: Box<dyn Hittable>) -> Self {
+        Csg{op, a, b}
+    }
+    pub fn union(a: Box<dyn Hittable>, b: Box<dyn Hittable>) -> Self {
+        Csg::new(CsgOp::Union, a, b)
+    }
+    pub fn intersection(a: Box<dyn Hittable>, b: Box<dyn Hittable>) -> Self {
+        Csg::new(CsgOp::Intersection, a, b)
+    }
+    // a minus b
+    pub fn difference(a: Box<dyn Hittable>, b: Box<dyn Hittable>) -> Self {
+        Csg::new(CsgOp::Difference, a, b)
+    }
+}
+
+// Boundaries of a closed object along the ray from t_min, alternating entries and exits
+// Returns whether the ray starts inside, deduced from the first boundary being an exit
+fn boundaries(object: &dyn Hittable, r: Ray, t_min: f64) -> (bool, Vec<HitRecord>) {
+    let mut list: Vec<HitRecord> = Vec::new();
+    let mut t = t_min;
+
+    while list.len() < MAX_BOUNDARIES {
+        let Some(rec) = object.hit(r, t, f64::INFINITY) else {
+            break;
+        };
+        t = rec.t + CSG_EPS;
+        // Skip duplicates, like two faces meeting on an edge
+        if let Some(last) = list.last() {
+            if last.front_face == rec.front_face {
+                continue;
+            }
+        }
+        list.push(rec);
+    }
+
+    let starts_inside = list.first().is_some_and(|rec| !rec.front_face);
+    (starts_inside, list)
+}
+
+impl Hittable for Csg {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+
+        let (mut in_a, list_a) = boundaries(self.a.as_ref(), r, t_min);
+        if list_a.is_empty() && !in_a && self.op != CsgOp::Union {
+            // Nothing of a along the ray, only a union can still be hit
+            return None;
+        }
+        let (mut in_b, list_b) = boundaries(self.b.as_ref(), r, t_min);
+
+        let inside = self.op.inside(in_a, in_b);
+        let (mut i, mut j) = (0, 0);
+
+        // Walk both lists in order, until the combined inside state changes
+        while i < list_a.len() || j < list_b.len() {
+            let from_a = j >= list_b.len() || (i < list_a.len() && list_a[i].t <= list_b[j].t);
+            let rec = if from_a {
+                in_a = !in_a;
+                i += 1;
+                &list_a[i - 1]
+            } else {
+                in_b = !in_b;
+                j += 1;
+                &list_b[j - 1]
+            };
+
+            if rec.t > t_max {
+                return None;
+            }
+            let now_inside = self.op.inside(in_a, in_b);
+            if now_inside == inside {
+                continue;
+            }
+
+            // Outward normal of the operand, flipped on the carved out surfaces
+            let mut outward = if rec.front_face { rec.normal } else { -rec.normal };
+            if !from_a && self.op == CsgOp::Difference {
+                outward = -outward;
+            }
+            let mut rec = rec.clone();
+            rec.set_face_normal(r, outward);
+            return Some(rec);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::vec3::Vec3;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::hittable::Sphere;
+
+    fn sphere(x: f64, radius: f64) -> Box<dyn Hittable> {
+        Box::new(Sphere::new(Vec3::new(x, 0.0, 0.0), radius, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))))
+    }
+
+    // Every boundary along the ray, with whether it is an entry
+    fn intervals(object: &Csg, r: Ray) -> Vec<(f64, bool)> {
+        let mut list = Vec::new();
+        let mut t = 0.001;
+        while let Some(rec) = object.hit(r, t, f64::INFINITY) {
+            // Shading normals face the ray, front_face tells entries from exits
+            assert!(rec.normal.dot(r.direction()) < 0.0);
+            list.push(((rec.t * 1e6).round() / 1e6, rec.front_face));
+            t = rec.t + 0.001;
+        }
+        list
+    }
+
+    fn along_x() -> Ray {
+        Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0))
+    }
+
+    #[test]
+    fn difference() {
+        // Hollow ball, through its shell twice
+        let hollow = Csg::difference(sphere(0.0, 1.0), sphere(0.0, 0.5));
+        assert_eq!(intervals(&hollow, along_x()), [(4.0, true), (4.5, false), (5.5, true), (6.0, false)]);
+
+        // Bitten ball, [-1, 1] minus [0, 2]
+        let bitten = Csg::difference(sphere(0.0, 1.0), sphere(1.0, 1.0));
+        assert_eq!(intervals(&bitten, along_x()), [(4.0, true), (5.0, false)]);
+
+        // Nothing left where b covers a
+        let empty = Csg::difference(sphere(0.0, 0.5), sphere(0.0, 1.0));
+        assert!(intervals(&empty, along_x()).is_empty());
+
+        // From inside the shell, the first boundary is the way out
+        let inside = Ray::new(Vec3::new(-0.75, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(intervals(&hollow, inside)[0], (0.25, false));
+    }
+
+    #[test]
+    fn union_and_intersection() {
+        let union = Csg::union(sphere(0.0, 1.0), sphere(1.0, 1.0));
+        assert_eq!(intervals(&union, along_x()), [(4.0, true), (7.0, false)]);
+        let intersection = Csg::intersection(sphere(0.0, 1.0), sphere(1.0, 1.0));
+        assert_eq!(intervals(&intersection, along_x()), [(5.0, true), (6.0, false)]);
+    }
+}
@@ -0,0 +1,258 @@
+use std::sync::Arc;
+use image::ImageError;
+use image::error::{ParameterError, ParameterErrorKind};
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::material::Scatter;
+use crate::hittable::{Hittable, HitRecord};
+use crate::hittable::aabb::Aabb;
+use crate::hittable::triangle;
+use crate::stats;
+
+// Terrain from a grid of heights, each cell being two triangles
+// Intersection walks a min-max quadtree of the cells, so only the few
+// cells the ray passes close to are tested.
+pub struct Heightfield {
+    origin: Vec3,
+    size: Vec3,
+    nx: usize,
+    nz: usize,
+    heights: Vec<f64>,
+    normals: Vec<Vec3>,
+    // Min-max pyramid, level 0 has one entry per cell
+    levels: Vec<MinMaxLevel>,
+    mat: Arc<dyn Scatter>,
+}
+
+struct MinMaxLevel {
+    w: usize,
+    h: usize,
+    bounds: Vec<(f64, f64)>,
+}
+
+impl Heightfield {
+    // heights are in [0,1], nx by nz samples, row major along X
+    // The terrain spans size.x by size.z from origin, and rises up to size.y
+    pub fn new(heights: Vec<f64>, nx: usize, nz: usize, origin: Vec3, size: Vec3, mat: Arc<dyn Scatter>) -> Self {
+        assert!(nx >= 2 && nz >= 2 && heights.len() == nx * nz, "Heightfield needs at least 2x2 samples");
+
+        let heights: Vec<f64> = heights.iter().map(|h| h * size.y()).collect();
+        let dx = size.x() / (nx - 1) as f64;
+        let dz = size.z() / (nz - 1) as f64;
+
+        // Vertex normals from central differences, one sided on the borders
+        let mut normals = Vec::with_capacity(nx * nz);
+        for j in 0..nz {
+            for i in 0..nx {
+                let (i0, i1) = (i.saturating_sub(1), usize::min(i + 1, nx - 1));
+                let (j0, j1) = (j.saturating_sub(1), usize::min(j + 1, nz - 1));
+                let dhdx = (heights[j*nx + i1] - heights[j*nx + i0]) / ((i1 - i0) as f64 * dx);
+                let dhdz = (heights[j1*nx + i] - heights[j0*nx + i]) / ((j1 - j0) as f64 * dz);
+                normals.push(Vec3::new(-dhdx, 1.0, -dhdz).unit());
+            }
+        }
+
+        // Per cell bounds, then merge 2x2 blocks up to a single root
+        let (w, h) = (nx - 1, nz - 1);
+        let mut bounds = Vec::with_capacity(w * h);
+        for j in 0..h {
+            for i in 0..w {
+                let corners = [heights[j*nx + i], heights[j*nx + i + 1], heights[(j+1)*nx + i], heights[(j+1)*nx + i + 1]];
+                let min = corners.iter().cloned().fold(f64::INFINITY, f64::min);
+                let max = corners.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                bounds.push((min, max));
+            }
+        }
+        let mut levels = vec![MinMaxLevel{w, h, bounds}];
+        while levels.last().map(|l| l.w > 1 || l.h > 1).unwrap() {
+            let prev = levels.last().unwrap();
+            let (w, h) = (prev.w.div_ceil(2), prev.h.div_ceil(2));
+            let mut bounds = vec![(f64::INFINITY, f64::NEG_INFINITY); w * h];
+            for j in 0..prev.h {
+                for i in 0..prev.w {
+                    let (min, max) = prev.bounds[j*prev.w + i];
+                    let b = &mut bounds[(j/2)*w + i/2];
+                    *b = (f64::min(b.0, min), f64::max(b.1, max));
+                }
+            }
+            levels.push(MinMaxLevel{w, h, bounds});
+        }
+
+        Heightfield{origin, size, nx, nz, heights, normals, levels, mat}
+    }
+
+    // Load heights from a grayscale image, black at origin.y, white at origin.y + size.y
+    // Image columns go along X and rows along Z, there must be 2 of each at least
+    pub fn from_image(filename: &str, origin: Vec3, size: Vec3, mat: Arc<dyn Scatter>) -> Result<Self, image::ImageError> {
+        let img = image::open(filename)?.into_luma16();
+        let (nx, nz) = (img.width() as usize, img.height() as usize);
+        if nx < 2 || nz < 2 {
+            return Err(ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::DimensionMismatch)));
+        }
+        let heights = img.pixels().map(|p| p.0[0] as f64 / u16::MAX as f64).collect();
+        Ok(Heightfield::new(heights, nx, nz, origin, size, mat))
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Vec3 {
+        Vec3::new(self.origin.x() + self.size.x() * i as f64 / (self.nx - 1) as f64,
+                  self.origin.y() + self.heights[j*self.nx + i],
+                  self.origin.z() + self.size.z() * j as f64 / (self.nz - 1) as f64)
+    }
+
+    // Bounds of the node i,j of a pyramid level
+    fn node_box(&self, level: usize, i: usize, j: usize) -> Aabb {
+        let cells_x = self.nx - 1;
+        let cells_z = self.nz - 1;
+        let (i0, i1) = (i << level, usize::min((i + 1) << level, cells_x));
+        let (j0, j1) = (j << level, usize::min((j + 1) << level, cells_z));
+        let (min, max) = self.levels[level].bounds[j*self.levels[level].w + i];
+        Aabb::new(Vec3::new(self.origin.x() + self.size.x() * i0 as f64 / cells_x as f64,
+                            self.origin.y() + min,
+                            self.origin.z() + self.size.z() * j0 as f64 / cells_z as f64),
+                  Vec3::new(self.origin.x() + self.size.x() * i1 as f64 / cells_x as f64,
+                            self.origin.y() + max,
+                            self.origin.z() + self.size.z() * j1 as f64 / cells_z as f64))
+    }
+
+    // Closest hit on the two triangles of cell i,j, counted as one intersection test
+    fn cell(&self, r: Ray, i: usize, j: usize, t_min: f64, t_max: &mut f64) -> Option<(f64, Vec3, Vec3)> {
+        stats::intersection_test();
+        let (v00, v10, v01, v11) = (self.vertex(i, j), self.vertex(i+1, j), self.vertex(i, j+1), self.vertex(i+1, j+1));
+        let n = |i: usize, j: usize| self.normals[j*self.nx + i];
+        let mut found = None;
+        // Interpolate the vertex normals for smooth shading
+        if let Some((t, b1, b2)) = triangle::intersect_uncounted(r, v00, v10, v11, t_min, *t_max) {
+            *t_max = t;
+            found = Some((t, (n(i, j) * (1.0 - b1 - b2) + n(i+1, j) * b1 + n(i+1, j+1) * b2).unit(), (v10 - v00).cross(v11 - v00)));
+        }
+        if let Some((t, b1, b2)) = triangle::intersect_uncounted(r, v00, v11, v01, t_min, *t_max) {
+            *t_max = t;
+            found = Some((t, (n(i, j) * (1.0 - b1 - b2) + n(i+1, j+1) * b1 + n(i, j+1) * b2).unit(), (v11 - v00).cross(v01 - v00)));
+        }
+        found
+    }
+
+    // Closest hit below a node as (t, shading normal, face normal), shrinking t_max as hits are found
+    fn traverse(&self, r: Ray, level: usize, i: usize, j: usize, t_min: f64, t_max: &mut f64) -> Option<(f64, Vec3, Vec3)> {
+        self.node_box(level, i, j).hit(r, t_min, *t_max)?;
+
+        if level == 0 {
+            return self.cell(r, i, j, t_min, t_max);
+        }
+
+        // Visit children roughly front to back, the nearest hit is kept either way
+        let child = &self.levels[level - 1];
+        let order_x = if r.direction().x() < 0.0 { [1, 0] } else { [0, 1] };
+        let order_z = if r.direction().z() < 0.0 { [1, 0] } else { [0, 1] };
+        let mut found = None;
+        for dj in order_z {
+            for di in order_x {
+                let (ci, cj) = (2*i + di, 2*j + dj);
+                if ci >= child.w || cj >= child.h {
+                    continue;
+                }
+                if let Some(hit) = self.traverse(r, level - 1, ci, cj, t_min, t_max) {
+                    found = Some(hit);
+                }
+            }
+        }
+        found
+    }
+}
+
+impl Hittable for Heightfield {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut closest = t_max;
+        let (t, normal, face_normal) = self.traverse(r, self.levels.len() - 1, 0, 0, t_min, &mut closest)?;
+
+        // UVs span the whole terrain
+        let p = r.at(t);
+        let u = (p.x() - self.origin.x()) / self.size.x();
+        let v = (p.z() - self.origin.z()) / self.size.z();
+        let mut rec = HitRecord::new(r, t, normal, u, v, self.mat.clone());
+        rec.set_geometric_normal(face_normal);
+        Some(rec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+
+    // Bumpy 13 by 9 samples, so the pyramid levels have odd sizes
+    fn terrain() -> Heightfield {
+        let (nx, nz) = (13, 9);
+        let heights = (0..nx * nz).map(|k| {
+            let (x, z) = ((k % nx) as f64, (k / nx) as f64);
+            0.5 + 0.3 * f64::sin(1.3 * x) * f64::cos(0.9 * z) + 0.2 * f64::sin(2.7 * x + 1.9 * z)
+        }).collect();
+        Heightfield::new(heights, nx, nz, Vec3::new(-2.0, 0.0, -1.0), Vec3::new(4.0, 1.0, 2.0),
+                         Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))))
+    }
+
+    // Closest hit over every cell, without the pyramid
+    fn brute_force(hf: &Heightfield, r: Ray) -> Option<(f64, Vec3, Vec3)> {
+        let mut t_max = f64::INFINITY;
+        let mut found = None;
+        for j in 0..hf.nz - 1 {
+            for i in 0..hf.nx - 1 {
+                if let Some(hit) = hf.cell(r, i, j, 0.001, &mut t_max) {
+                    found = Some(hit);
+                }
+            }
+        }
+        found
+    }
+
+    #[test]
+    fn pyramid_matches_brute_force() {
+        let hf = terrain();
+        let mut rng = fastrand::Rng::with_seed(3);
+        let mut hits = 0;
+        for _ in 0..2000 {
+            // From above and around the terrain, towards a point on or near it
+            let origin = Vec3::new(rng.f64() * 8.0 - 4.0, rng.f64() * 3.0, rng.f64() * 6.0 - 3.0);
+            let target = Vec3::new(rng.f64() * 5.0 - 2.5, rng.f64(), rng.f64() * 3.0 - 1.5);
+            let r = Ray::new(origin, target - origin);
+
+            let mut t_max = f64::INFINITY;
+            let traversed = hf.traverse(r, hf.levels.len() - 1, 0, 0, 0.001, &mut t_max);
+            match (traversed, brute_force(&hf, r)) {
+                (Some((t, normal, face)), Some((bt, bnormal, bface))) => {
+                    assert!((t - bt).abs() < 1e-9);
+                    assert!((normal - bnormal).length() < 1e-9 && (face.unit() - bface.unit()).length() < 1e-9);
+                    hits += 1;
+                }
+                (None, None) => {}
+                (a, b) => panic!("pyramid {:?} and brute force {:?} disagree", a.map(|h| h.0), b.map(|h| h.0)),
+            }
+        }
+        assert!(hits > 500);
+    }
+
+    #[test]
+    fn counts_cells() {
+        let hf = terrain();
+        stats::take();
+        let r = Ray::new(Vec3::new(0.1, 3.0, 0.1), Vec3::new(0.0, -1.0, 0.0));
+        assert!(hf.hit(r, 0.001, f64::INFINITY).is_some());
+        let tests = stats::take().intersection_tests;
+        // Straight down, only the cells around the ray are tested
+        assert!((1..=4).contains(&tests));
+
+        brute_force(&hf, r);
+        assert_eq!(stats::take().intersection_tests, 12 * 8);
+    }
+
+    #[test]
+    fn too_small_image() {
+        let filename = std::env::temp_dir().join("rustracer_heightfield_1x4.png");
+        image::save_buffer(&filename, &[0, 64, 128, 255], 1, 4, image::ColorType::L8).unwrap();
+        let hf = Heightfield::from_image(filename.to_str().unwrap(), Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0),
+                                         Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+        std::fs::remove_file(&filename).unwrap();
+        assert!(matches!(hf, Err(ImageError::Parameter(_))));
+    }
+}
@@ -0,0 +1,172 @@
+use std::sync::Arc;
+use crate::vec3::Vec3;
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::material::Scatter;
+use crate::hittable::{Hittable, HitRecord};
+use crate::hittable::aabb::Aabb;
+use crate::hittable::triangle;
+
+const BVH_LEAF_SIZE: usize = 4;
+
+// Indexed triangle mesh, with a bounding volume hierarchy over its triangles
+// Normals, UVs and colors are optional per vertex attributes, leave them empty when absent:
+// the face normal, the barycentric coordinates and white are then used instead.
+pub struct Mesh {
+    positions: Vec<Vec3>,
+    normals: Vec<Vec3>,
+    uvs: Vec<(f64, f64)>,
+    colors: Vec<Color>,
+    triangles: Vec<[usize; 3]>,
+    nodes: Vec<BvhNode>,
+    mat: Arc<dyn Scatter>,
+}
+
+// Inner nodes have count 0 and their children at first and first + 1,
+// leaves own the triangles [first, first + count)
+struct BvhNode {
+    bounds: Aabb,
+    first: usize,
+    count: usize,
+}
+
+impl Mesh {
+    pub fn new(positions: Vec<Vec3>, normals: Vec<Vec3>, uvs: Vec<(f64, f64)>, colors: Vec<Color>, triangles: Vec<[usize; 3]>, mat: Arc<dyn Scatter>) -> Self {
+        assert!(normals.is_empty() || normals.len() == positions.len(), "Mesh normals do not match its positions");
+        assert!(uvs.is_empty() || uvs.len() == positions.len(), "Mesh UVs do not match its positions");
+        assert!(colors.is_empty() || colors.len() == positions.len(), "Mesh colors do not match its positions");
+        let mut mesh = Mesh{positions, normals, uvs, colors, triangles, nodes: Vec::new(), mat};
+        mesh.build_bvh();
+        mesh
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.positions.iter().fold(Aabb::empty(), |b, p| b.grow(*p))
+    }
+
+    fn build_bvh(&mut self) {
+        self.nodes.clear();
+        if self.triangles.is_empty() {
+            return;
+        }
+        let count = self.triangles.len();
+        self.nodes.push(BvhNode{bounds: Aabb::empty(), first: 0, count});
+        self.subdivide(0);
+    }
+
+    // Split a node at the middle of its centroids' longest axis
+    fn subdivide(&mut self, node: usize) {
+        let (first, count) = (self.nodes[node].first, self.nodes[node].count);
+        let tris = &mut self.triangles[first..first + count];
+
+        let mut bounds = Aabb::empty();
+        let mut centroids = Aabb::empty();
+        for tri in tris.iter() {
+            let b = Aabb::empty().grow(self.positions[tri[0]]).grow(self.positions[tri[1]]).grow(self.positions[tri[2]]);
+            bounds = bounds.union(b);
+            centroids = centroids.grow(b.center());
+        }
+        self.nodes[node].bounds = bounds;
+        if count <= BVH_LEAF_SIZE {
+            return;
+        }
+
+        let extent = centroids.size();
+        let axis = if extent.x() > extent.y() && extent.x() > extent.z() { 0 } else if extent.y() > extent.z() { 1 } else { 2 };
+        let component = |v: Vec3| match axis { 0 => v.x(), 1 => v.y(), _ => v.z() };
+
+        // Sort along the axis and cut in the middle, which always makes progress
+        let positions = &self.positions;
+        let centroid = |tri: &[usize; 3]| component(positions[tri[0]] + positions[tri[1]] + positions[tri[2]]);
+        tris.sort_by(|a, b| centroid(a).partial_cmp(&centroid(b)).unwrap_or(std::cmp::Ordering::Equal));
+        let half = count / 2;
+
+        let left = self.nodes.len();
+        self.nodes.push(BvhNode{bounds: Aabb::empty(), first, count: half});
+        self.nodes.push(BvhNode{bounds: Aabb::empty(), first: first + half, count: count - half});
+        self.nodes[node].first = left;
+        self.nodes[node].count = 0;
+        self.subdivide(left);
+        self.subdivide(left + 1);
+    }
+
+    // Derivative of the position along u over a triangle, from its UVs or its barycentric coordinates
+    fn tangent(&self, tri: &[usize; 3]) -> Vec3 {
+        let [i0, i1, i2] = *tri;
+        let e1 = self.positions[i1] - self.positions[i0];
+        let e2 = self.positions[i2] - self.positions[i0];
+        if self.uvs.is_empty() {
+            return e1;
+        }
+        let (du1, dv1) = (self.uvs[i1].0 - self.uvs[i0].0, self.uvs[i1].1 - self.uvs[i0].1);
+        let (du2, dv2) = (self.uvs[i2].0 - self.uvs[i0].0, self.uvs[i2].1 - self.uvs[i0].1);
+        let det = du1 * dv2 - du2 * dv1;
+        if det.abs() < 1e-12 {
+            return Vec3::new(0.0, 0.0, 0.0);
+        }
+        (e1 * dv2 - e2 * dv1) / det
+    }
+
+    // Interpolate the vertex attributes of a triangle
+    fn hit_record(&self, r: Ray, t: f64, tri: &[usize; 3], b1: f64, b2: f64) -> HitRecord {
+        let b0 = 1.0 - b1 - b2;
+        let [i0, i1, i2] = *tri;
+
+        let face_normal = (self.positions[i1] - self.positions[i0]).cross(self.positions[i2] - self.positions[i0]);
+        let normal = if self.normals.is_empty() {
+            face_normal.unit()
+        } else {
+            (self.normals[i0] * b0 + self.normals[i1] * b1 + self.normals[i2] * b2).unit()
+        };
+        let (u, v) = if self.uvs.is_empty() {
+            (b1, b2)
+        } else {
+            (self.uvs[i0].0 * b0 + self.uvs[i1].0 * b1 + self.uvs[i2].0 * b2,
+             self.uvs[i0].1 * b0 + self.uvs[i1].1 * b1 + self.uvs[i2].1 * b2)
+        };
+        let mut rec = HitRecord::new(r, t, normal, u, v, self.mat.clone());
+        rec.set_geometric_normal(face_normal);
+        rec.tangent = self.tangent(tri);
+        if !self.colors.is_empty() {
+            rec.color = self.colors[i0] * b0 + self.colors[i1] * b1 + self.colors[i2] * b2;
+        }
+        rec
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut closest = t_max;
+        let mut found: Option<(f64, usize, f64, f64)> = None;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.bounds.hit(r, t_min, closest).is_none() {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.first);
+                stack.push(node.first + 1);
+                continue;
+            }
+            for i in node.first..node.first + node.count {
+                let [i0, i1, i2] = self.triangles[i];
+                if let Some((t, b1, b2)) = triangle::intersect(r, self.positions[i0], self.positions[i1], self.positions[i2], t_min, closest) {
+                    closest = t;
+                    found = Some((t, i, b1, b2));
+                }
+            }
+        }
+
+        let (t, i, b1, b2) = found?;
+        Some(self.hit_record(r, t, &self.triangles[i], b1, b2))
+    }
+}
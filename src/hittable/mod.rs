@@ -1,8 +1,19 @@
 use std::boxed::Box;
 use std::sync::Arc;
 use crate::vec3::Vec3;
+use crate::color::Color;
 use crate::ray::Ray;
 use crate::material::Scatter;
+use crate::stats;
+
+pub mod quad;
+pub mod revolution;
+pub mod csg;
+pub mod sdf;
+pub mod heightfield;
+pub mod aabb;
+pub mod triangle;
+pub mod mesh;
 
 pub trait Hittable: Send + Sync {
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
@@ -11,29 +22,90 @@ pub trait Hittable: Send + Sync {
 #[derive(Clone)]
 pub struct HitRecord {
     pub p: Vec3,
+    // Shading normal, which normal and bump maps may perturb
     pub normal: Vec3,
+    // Normal of the actual surface, on the side of the shading normal which faces the incoming ray
+    pub geometric_normal: Vec3,
+    // Direction of increasing u on the surface, zero when the primitive has none
+    pub tangent: Vec3,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
+    // Per vertex color of meshes, white for everything else
+    pub color: Color,
+    // Index of the world object hit, from 1, filled in by the world
+    pub object: usize,
     pub mat: Arc<dyn Scatter>,
 }
 
 impl HitRecord {
+    pub fn new(r: Ray, t: f64, outward_normal: Vec3, u: f64, v: f64, mat: Arc<dyn Scatter>) -> Self {
+        let mut rec = HitRecord {
+            p: r.at(t),
+            normal: outward_normal,
+            geometric_normal: outward_normal,
+            tangent: Vec3::new(0.0, 0.0, 0.0),
+            t,
+            u,
+            v,
+            front_face: false,
+            color: Color::new(1.0, 1.0, 1.0),
+            object: 0,
+            mat,
+        };
+        rec.set_face_normal(r, outward_normal);
+        rec
+    }
+
     fn set_face_normal(&mut self, r: Ray, outward_normal: Vec3) {
         self.front_face = r.direction().dot(outward_normal) < 0.0;
         self.normal = if self.front_face { outward_normal} else {-outward_normal};
+        self.geometric_normal = self.normal;
+    }
+
+    // Normal of the actual surface, for primitives interpolating their normals
+    // It faces the same side as the shading normal.
+    pub fn set_geometric_normal(&mut self, face_normal: Vec3) {
+        let n = face_normal.unit();
+        self.geometric_normal = if n.dot(self.normal) < 0.0 { -n } else { n };
+    }
+
+    // Orthonormal tangent and bitangent around the shading normal, following
+    // the UVs where the primitive provides a tangent
+    pub fn tangent_frame(&self) -> (Vec3, Vec3) {
+        let t = self.tangent - self.normal * self.tangent.dot(self.normal);
+        if t.near_zero() {
+            return Vec3::orthonormal_basis(self.normal);
+        }
+        let t = t.unit();
+        (t, self.normal.cross(t))
     }
 }
 
 pub type World = Vec<Box<dyn Hittable>>;
 
+const CUTOUT_EPS: f64 = 1e-6;   // Step past a transparent hit before looking again
+
 impl Hittable for World {
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         let mut tmp_rec = None;
         let mut closest_so_far = t_max;
-        for object in self {
-            if let Some(rec) = object.hit(r, t_min, closest_so_far) {
+        // Transparent parts of cutouts are only skipped here, objects made of other
+        // objects must not test the opacity again
+        for (index, object) in self.iter().enumerate() {
+            let mut t_start = t_min;
+            while let Some(mut rec) = object.hit(r, t_start, closest_so_far) {
+                // Transparent parts of cutouts let the ray go on, maybe to the same object
+                let opacity = rec.mat.opacity(&rec);
+                if opacity < 1.0 && fastrand::f64() >= opacity {
+                    t_start = rec.t + CUTOUT_EPS;
+                    continue;
+                }
                 closest_so_far = rec.t;
+                rec.object = index + 1;
                 tmp_rec = Some(rec);
+                break;
             }
         }
         tmp_rec
@@ -50,13 +122,22 @@ pub struct Sphere {
 
 impl Sphere {
     pub fn new(c: Vec3, r: f64, mat: Arc<dyn Scatter>) -> Self {
-        Sphere{center: c, radius: r, mat: mat}
+        Sphere{center: c, radius: r, mat}
+    }
+
+    // Spherical coordinates of a point on the unit sphere, mapped to [0,1]
+    // u is the angle around the Y axis from X=-1, v the angle from Y=-1 to Y=+1
+    fn get_uv(p: Vec3) -> (f64, f64) {
+        let theta = f64::acos(-p.y());
+        let phi = f64::atan2(-p.z(), p.x()) + std::f64::consts::PI;
+        (phi / (2.0 * std::f64::consts::PI), theta / std::f64::consts::PI)
     }
 }
 
 impl Hittable for Sphere {
     fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
 
+        stats::intersection_test();
         let oc: Vec3 = r.origin() - self.center;
         let a: f64 = r.direction().length_squared();
         let half_b: f64 = oc.dot(r.direction());
@@ -77,17 +158,11 @@ impl Hittable for Sphere {
             }
         }
 
-        let p = r.at(root);
-        let mut rec = HitRecord {
-            t: root,
-            p: p,
-            normal: Vec3::new(0.0, 0.0, 0.0),
-            front_face: false,
-            mat: self.mat.clone(),
-        };
-
-        let outward_normal = (rec.p - self.center) / self.radius;
-        rec.set_face_normal(r, outward_normal);
+        let outward_normal = (r.at(root) - self.center) / self.radius;
+        let (u, v) = Sphere::get_uv(outward_normal);
+        let mut rec = HitRecord::new(r, root, outward_normal, u, v, self.mat.clone());
+        // Derivative of the point along u, around the Y axis
+        rec.tangent = Vec3::new(outward_normal.z(), 0.0, -outward_normal.x());
         Some(rec)
     }
 }
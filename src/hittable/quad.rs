@@ -0,0 +1,140 @@
+use std::sync::Arc;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::material::Scatter;
+use crate::hittable::{Hittable, HitRecord};
+use crate::stats;
+
+// Infinite plane going through a point
+// UVs are the world space coordinates along two tangent axes, textures are expected to wrap
+#[derive(Clone)]
+pub struct Plane {
+    point: Vec3,
+    normal: Vec3,
+    tangent: Vec3,
+    bitangent: Vec3,
+    mat: Arc<dyn Scatter>,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, mat: Arc<dyn Scatter>) -> Self {
+        let normal = normal.unit();
+        let (tangent, bitangent) = Vec3::orthonormal_basis(normal);
+        Plane{point, normal, tangent, bitangent, mat}
+    }
+}
+
+impl Hittable for Plane {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+
+        stats::intersection_test();
+        let denom = self.normal.dot(r.direction());
+        // Ray parallel to the plane
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = (self.point - r.origin()).dot(self.normal) / denom;
+        if t < t_min || t_max < t {
+            return None;
+        }
+
+        let local = r.at(t) - self.point;
+        Some(HitRecord::new(r, t, self.normal, local.dot(self.tangent), local.dot(self.bitangent), self.mat.clone()))
+    }
+}
+
+
+// Parallelogram spanned by the edges u and v from the corner q
+// The outward normal is u x v, UVs go from 0 to 1 along each edge
+#[derive(Clone)]
+pub struct Quad {
+    q: Vec3,
+    u: Vec3,
+    v: Vec3,
+    normal: Vec3,
+    d: f64,
+    w: Vec3,
+    mat: Arc<dyn Scatter>,
+}
+
+impl Quad {
+    pub fn new(q: Vec3, u: Vec3, v: Vec3, mat: Arc<dyn Scatter>) -> Self {
+        let n = u.cross(v);
+        let normal = n.unit();
+        let d = normal.dot(q);
+        let w = n / n.dot(n);
+        Quad{q, u, v, normal, d, w, mat}
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+
+        stats::intersection_test();
+        let denom = self.normal.dot(r.direction());
+        // Ray parallel to the quad
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = (self.d - self.normal.dot(r.origin())) / denom;
+        if t < t_min || t_max < t {
+            return None;
+        }
+
+        // Express the hit point in the (u, v) frame and check it lies inside
+        let planar = r.at(t) - self.q;
+        let alpha = self.w.dot(planar.cross(self.v));
+        let beta = self.w.dot(self.u.cross(planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        Some(HitRecord::new(r, t, self.normal, alpha, beta, self.mat.clone()))
+    }
+}
+
+
+// Axis aligned box between two opposite corners, made of six outward facing quads
+// The sides are searched directly rather than as a World, so cutout materials get
+// their opacity tested once, by the top level World.
+pub struct Cuboid {
+    sides: Vec<Quad>,
+}
+
+impl Cuboid {
+    pub fn new(a: Vec3, b: Vec3, mat: Arc<dyn Scatter>) -> Self {
+        let min = Vec3::new(f64::min(a.x(), b.x()), f64::min(a.y(), b.y()), f64::min(a.z(), b.z()));
+        let max = Vec3::new(f64::max(a.x(), b.x()), f64::max(a.y(), b.y()), f64::max(a.z(), b.z()));
+
+        let dx = Vec3::new(max.x() - min.x(), 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y() - min.y(), 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z() - min.z());
+
+        let sides = vec![
+            // Front and back
+            Quad::new(Vec3::new(min.x(), min.y(), max.z()), dx, dy, mat.clone()),
+            Quad::new(Vec3::new(max.x(), min.y(), min.z()), -dx, dy, mat.clone()),
+            // Right and left
+            Quad::new(Vec3::new(max.x(), min.y(), max.z()), -dz, dy, mat.clone()),
+            Quad::new(Vec3::new(min.x(), min.y(), min.z()), dz, dy, mat.clone()),
+            // Top and bottom
+            Quad::new(Vec3::new(min.x(), max.y(), max.z()), dx, -dz, mat.clone()),
+            Quad::new(Vec3::new(min.x(), min.y(), min.z()), dx, dz, mat),
+        ];
+        Cuboid{sides}
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut closest = None;
+        let mut closest_so_far = t_max;
+        for side in &self.sides {
+            if let Some(rec) = side.hit(r, t_min, closest_so_far) {
+                closest_so_far = rec.t;
+                closest = Some(rec);
+            }
+        }
+        closest
+    }
+}
@@ -0,0 +1,459 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::material::Scatter;
+use crate::hittable::{Hittable, HitRecord};
+use crate::stats;
+
+// Surfaces of revolution, defined in a local frame where the axis is +Y
+// The frame is orthonormal, so distances along a ray are the same in both spaces
+#[derive(Debug, Clone)]
+struct Frame {
+    origin: Vec3,
+    x: Vec3,
+    y: Vec3,
+    z: Vec3,
+}
+
+impl Frame {
+    fn new(origin: Vec3, axis: Vec3) -> Self {
+        let y = axis.unit();
+        let (z, x) = Vec3::orthonormal_basis(y);
+        Frame{origin, x, y, z}
+    }
+    fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(v.dot(self.x), v.dot(self.y), v.dot(self.z))
+    }
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        self.x * v.x() + self.y * v.y() + self.z * v.z()
+    }
+    fn ray_to_local(&self, r: Ray) -> Ray {
+        Ray::new(self.to_local(r.origin() - self.origin), self.to_local(r.direction()))
+    }
+}
+
+// Angle around the local Y axis, mapped to [0,1]
+fn azimuth(p: Vec3) -> f64 {
+    (f64::atan2(-p.z(), p.x()) + PI) / (2.0 * PI)
+}
+
+// Local hit on the plane y = height, inside the given radius
+fn hit_cap(r: Ray, height: f64, radius: f64, t_min: f64, t_max: f64) -> Option<f64> {
+    if r.direction().y().abs() < 1e-8 {
+        return None;
+    }
+    let t = (height - r.origin().y()) / r.direction().y();
+    if t < t_min || t_max < t {
+        return None;
+    }
+    let p = r.at(t);
+    if p.x()*p.x() + p.z()*p.z() > radius*radius {
+        return None;
+    }
+    Some(t)
+}
+
+
+// Flat disk, facing the normal direction
+#[derive(Clone)]
+pub struct Disk {
+    frame: Frame,
+    radius: f64,
+    mat: Arc<dyn Scatter>,
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f64, mat: Arc<dyn Scatter>) -> Self {
+        Disk{frame: Frame::new(center, normal), radius, mat}
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+
+        stats::intersection_test();
+        let lr = self.frame.ray_to_local(r);
+        let t = hit_cap(lr, 0.0, self.radius, t_min, t_max)?;
+
+        // Polar UVs, u around the center, v from the center to the rim
+        let p = lr.at(t);
+        let v = f64::sqrt(p.x()*p.x() + p.z()*p.z()) / self.radius;
+        Some(HitRecord::new(r, t, self.frame.y, azimuth(p), v, self.mat.clone()))
+    }
+}
+
+
+// Finite cylinder standing on its base center, along the axis
+// Open tubes are seen from the inside through set_face_normal
+#[derive(Clone)]
+pub struct Cylinder {
+    frame: Frame,
+    radius: f64,
+    height: f64,
+    capped: bool,
+    mat: Arc<dyn Scatter>,
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3, axis: Vec3, radius: f64, height: f64, capped: bool, mat: Arc<dyn Scatter>) -> Self {
+        Cylinder{frame: Frame::new(base, axis), radius, height, capped, mat}
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+
+        stats::intersection_test();
+        let lr = self.frame.ray_to_local(r);
+        let o = lr.origin();
+        let d = lr.direction();
+
+        // (t, local outward normal, u, v) of the closest hit so far
+        let mut closest: Option<(f64, Vec3, f64, f64)> = None;
+        let mut t_far = t_max;
+
+        // Side, x² + z² = radius²
+        let a = d.x()*d.x() + d.z()*d.z();
+        if a > 1e-12 {
+            let half_b = o.x()*d.x() + o.z()*d.z();
+            let c = o.x()*o.x() + o.z()*o.z() - self.radius*self.radius;
+            let discriminant = half_b*half_b - a*c;
+            if discriminant >= 0.0 {
+                let sqrtd = f64::sqrt(discriminant);
+                for t in [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a] {
+                    if t < t_min || t_far < t {
+                        continue;
+                    }
+                    let p = lr.at(t);
+                    if p.y() < 0.0 || p.y() > self.height {
+                        continue;
+                    }
+                    let normal = Vec3::new(p.x(), 0.0, p.z()) / self.radius;
+                    closest = Some((t, normal, azimuth(p), p.y() / self.height));
+                    t_far = t;
+                    break;
+                }
+            }
+        }
+
+        // Caps, with polar UVs
+        if self.capped {
+            for (height, ny) in [(0.0, -1.0), (self.height, 1.0)] {
+                if let Some(t) = hit_cap(lr, height, self.radius, t_min, t_far) {
+                    let p = lr.at(t);
+                    let v = f64::sqrt(p.x()*p.x() + p.z()*p.z()) / self.radius;
+                    closest = Some((t, Vec3::new(0.0, ny, 0.0), azimuth(p), v));
+                    t_far = t;
+                }
+            }
+        }
+
+        let (t, normal, u, v) = closest?;
+        Some(HitRecord::new(r, t, self.frame.to_world(normal), u, v, self.mat.clone()))
+    }
+}
+
+
+// Finite cone, base disk centered on base, apex at base + axis * height
+#[derive(Clone)]
+pub struct Cone {
+    frame: Frame,
+    radius: f64,
+    height: f64,
+    capped: bool,
+    mat: Arc<dyn Scatter>,
+}
+
+impl Cone {
+    pub fn new(base: Vec3, axis: Vec3, radius: f64, height: f64, capped: bool, mat: Arc<dyn Scatter>) -> Self {
+        Cone{frame: Frame::new(base, axis), radius, height, capped, mat}
+    }
+}
+
+impl Hittable for Cone {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+
+        stats::intersection_test();
+        let lr = self.frame.ray_to_local(r);
+        let o = lr.origin();
+        let d = lr.direction();
+
+        // x² + z² = (k (height - y))², with k the slope of the side
+        let k = self.radius / self.height;
+        let k2 = k*k;
+        let oy = self.height - o.y();
+        let dy = -d.y();
+        let a = d.x()*d.x() + d.z()*d.z() - k2*dy*dy;
+        let half_b = o.x()*d.x() + o.z()*d.z() - k2*oy*dy;
+        let c = o.x()*o.x() + o.z()*o.z() - k2*oy*oy;
+
+        let mut roots = Vec::with_capacity(2);
+        if a.abs() < 1e-12 {
+            // Ray parallel to the side, a single root
+            if half_b.abs() > 1e-12 {
+                roots.push(-c / (2.0 * half_b));
+            }
+        } else {
+            let discriminant = half_b*half_b - a*c;
+            if discriminant >= 0.0 {
+                let sqrtd = f64::sqrt(discriminant);
+                let (t0, t1) = ((-half_b - sqrtd) / a, (-half_b + sqrtd) / a);
+                roots.push(f64::min(t0, t1));
+                roots.push(f64::max(t0, t1));
+            }
+        }
+
+        let mut closest: Option<(f64, Vec3, f64, f64)> = None;
+        let mut t_far = t_max;
+
+        for t in roots {
+            if t < t_min || t_far < t {
+                continue;
+            }
+            // Reject the mirrored nappe and anything past the base
+            let p = lr.at(t);
+            if p.y() < 0.0 || p.y() > self.height {
+                continue;
+            }
+            let normal = Vec3::new(p.x(), k2 * (self.height - p.y()), p.z()).unit();
+            closest = Some((t, normal, azimuth(p), p.y() / self.height));
+            t_far = t;
+            break;
+        }
+
+        if self.capped {
+            if let Some(t) = hit_cap(lr, 0.0, self.radius, t_min, t_far) {
+                let p = lr.at(t);
+                let v = f64::sqrt(p.x()*p.x() + p.z()*p.z()) / self.radius;
+                closest = Some((t, Vec3::new(0.0, -1.0, 0.0), azimuth(p), v));
+            }
+        }
+
+        let (t, normal, u, v) = closest?;
+        Some(HitRecord::new(r, t, self.frame.to_world(normal), u, v, self.mat.clone()))
+    }
+}
+
+
+// Torus around the axis, major radius to the center of the tube, minor radius of the tube
+#[derive(Clone)]
+pub struct Torus {
+    frame: Frame,
+    major: f64,
+    minor: f64,
+    mat: Arc<dyn Scatter>,
+}
+
+impl Torus {
+    pub fn new(center: Vec3, axis: Vec3, major: f64, minor: f64, mat: Arc<dyn Scatter>) -> Self {
+        Torus{frame: Frame::new(center, axis), major, minor, mat}
+    }
+}
+
+impl Hittable for Torus {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+
+        stats::intersection_test();
+        let lr = self.frame.ray_to_local(r);
+        let len = lr.direction().length();
+        let d = lr.direction() / len;
+
+        // Bounding sphere, also used to move the origin close to the torus,
+        // which keeps the quartic coefficients small and the roots accurate
+        let bound = self.major + self.minor;
+        let oc = lr.origin();
+        let half_b = oc.dot(d);
+        let discriminant = half_b*half_b - (oc.length_squared() - bound*bound);
+        if discriminant < 0.0 {
+            return None;
+        }
+        let t_start = f64::max(-half_b - f64::sqrt(discriminant), 0.0);
+        let o = oc + d * t_start;
+
+        // (|p|² - R² - r²)² = 4R²(r² - y²), expanded in t
+        let r2 = self.major * self.major;
+        let e = o.length_squared() - r2 - self.minor * self.minor;
+        let f = o.dot(d);
+        let coeffs = [
+            e*e - 4.0*r2*(self.minor*self.minor - o.y()*o.y()),
+            4.0*f*e + 8.0*r2*o.y()*d.y(),
+            4.0*f*f + 2.0*e + 4.0*r2*d.y()*d.y(),
+            4.0*f,
+            1.0,
+        ];
+
+        // Near degenerate cases may give NaNs, which are no intersections
+        let mut roots: Vec<f64> = solve_quartic(coeffs).into_iter().filter(|s| s.is_finite()).collect();
+        roots.sort_by(f64::total_cmp);
+        let t = roots.into_iter()
+            .map(|s| (t_start + s) / len)
+            .find(|t| *t >= t_min && *t <= t_max)?;
+
+        // Outward normal, from the closest point of the tube's center circle
+        let p = lr.at(t);
+        let ring = Vec3::new(p.x(), 0.0, p.z()).unit() * self.major;
+        let normal = (p - ring) / self.minor;
+
+        // u around the axis, v around the tube
+        let u = azimuth(p);
+        let radial = Vec3::new(p.x(), 0.0, p.z()).length() - self.major;
+        let v = (f64::atan2(p.y(), radial) + PI) / (2.0 * PI);
+        Some(HitRecord::new(r, t, self.frame.to_world(normal), u, v, self.mat.clone()))
+    }
+}
+
+
+// Polynomial solvers for the torus, after Jochen Schwarze in Graphics Gems
+// Coefficients are given from the constant term up
+const EQN_EPS: f64 = 1e-9;
+
+fn is_zero(x: f64) -> bool {
+    x.abs() < EQN_EPS
+}
+
+fn solve_quadric(c: [f64; 3]) -> Vec<f64> {
+    // x² + 2px + q = 0
+    let p = c[1] / (2.0 * c[2]);
+    let q = c[0] / c[2];
+    let d = p*p - q;
+
+    if is_zero(d) {
+        vec![-p]
+    } else if d < 0.0 {
+        vec![]
+    } else {
+        let sqrt_d = f64::sqrt(d);
+        vec![sqrt_d - p, -sqrt_d - p]
+    }
+}
+
+fn solve_cubic(c: [f64; 4]) -> Vec<f64> {
+    // x³ + Ax² + Bx + C = 0
+    let a = c[2] / c[3];
+    let b = c[1] / c[3];
+    let c = c[0] / c[3];
+
+    // Substitute x = y - A/3 to eliminate the quadric term, y³ + 3py + 2q = 0
+    let sq_a = a*a;
+    let p = (-sq_a / 3.0 + b) / 3.0;
+    let q = (2.0 / 27.0 * a * sq_a - a * b / 3.0 + c) / 2.0;
+
+    let cb_p = p*p*p;
+    let d = q*q + cb_p;
+
+    let mut s = if is_zero(d) {
+        if is_zero(q) {
+            vec![0.0]
+        } else {
+            let u = f64::cbrt(-q);
+            vec![2.0 * u, -u]
+        }
+    } else if d < 0.0 {
+        // Three real solutions
+        let phi = f64::acos(-q / f64::sqrt(-cb_p)) / 3.0;
+        let t = 2.0 * f64::sqrt(-p);
+        vec![t * f64::cos(phi), -t * f64::cos(phi + PI / 3.0), -t * f64::cos(phi - PI / 3.0)]
+    } else {
+        let sqrt_d = f64::sqrt(d);
+        vec![f64::cbrt(sqrt_d - q) - f64::cbrt(sqrt_d + q)]
+    };
+
+    for x in s.iter_mut() {
+        *x -= a / 3.0;
+    }
+    s
+}
+
+fn solve_quartic(c: [f64; 5]) -> Vec<f64> {
+    // x⁴ + Ax³ + Bx² + Cx + D = 0
+    let a = c[3] / c[4];
+    let b = c[2] / c[4];
+    let cc = c[1] / c[4];
+    let d = c[0] / c[4];
+
+    // Substitute x = y - A/4 to eliminate the cubic term, y⁴ + py² + qy + r = 0
+    let sq_a = a*a;
+    let p = -3.0 / 8.0 * sq_a + b;
+    let q = sq_a * a / 8.0 - a * b / 2.0 + cc;
+    let r = -3.0 / 256.0 * sq_a * sq_a + sq_a * b / 16.0 - a * cc / 4.0 + d;
+
+    let mut s = if is_zero(r) {
+        // No absolute term, y(y³ + py + q) = 0
+        let mut s = solve_cubic([q, p, 0.0, 1.0]);
+        s.push(0.0);
+        s
+    } else {
+        // Solve the resolvent cubic and take its one real solution
+        let z = solve_cubic([r * p / 2.0 - q * q / 8.0, -r, -p / 2.0, 1.0])[0];
+
+        // Build two quadric equations
+        let u = z*z - r;
+        let v = 2.0*z - p;
+        let u = if is_zero(u) { 0.0 } else if u > 0.0 { f64::sqrt(u) } else { return vec![] };
+        let v = if is_zero(v) { 0.0 } else if v > 0.0 { f64::sqrt(v) } else { return vec![] };
+
+        let mut s = solve_quadric([z - u, if q < 0.0 { -v } else { v }, 1.0]);
+        s.extend(solve_quadric([z + u, if q < 0.0 { v } else { -v }, 1.0]));
+        s
+    };
+
+    // Resubstitute, then polish with Newton iterations on the original polynomial
+    for x in s.iter_mut() {
+        *x -= a / 4.0;
+        for _ in 0..2 {
+            let f = (((c[4] * *x + c[3]) * *x + c[2]) * *x + c[1]) * *x + c[0];
+            let df = ((4.0 * c[4] * *x + 3.0 * c[3]) * *x + 2.0 * c[2]) * *x + c[1];
+            if df.abs() > 1e-12 {
+                *x -= f / df;
+            }
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+
+    fn sorted(mut roots: Vec<f64>) -> Vec<f64> {
+        roots.sort_by(f64::total_cmp);
+        roots
+    }
+
+    fn assert_roots(c: [f64; 5], expected: &[f64]) {
+        let roots = sorted(solve_quartic(c));
+        assert_eq!(roots.len(), expected.len(), "{:?}", roots);
+        for (x, e) in roots.iter().zip(expected) {
+            assert!((x - e).abs() < 1e-9, "{:?}", roots);
+        }
+    }
+
+    #[test]
+    fn quartic_roots() {
+        // (x-1)(x-2)(x-3)(x-4)
+        assert_roots([24.0, -50.0, 35.0, -10.0, 1.0], &[1.0, 2.0, 3.0, 4.0]);
+        // (x²+1)(x-1)(x+2)
+        assert_roots([-2.0, 1.0, -1.0, 1.0, 1.0], &[-2.0, 1.0]);
+        // Scaled, 2(x²-1)(x²-4)
+        assert_roots([8.0, 0.0, -10.0, 0.0, 2.0], &[-2.0, -1.0, 1.0, 2.0]);
+        // No absolute term after the substitution, x²(x-1)(x+1)
+        assert_roots([0.0, 0.0, -1.0, 0.0, 1.0], &[-1.0, 0.0, 0.0, 1.0]);
+        // x⁴+1
+        assert_roots([1.0, 0.0, 0.0, 0.0, 1.0], &[]);
+    }
+
+    #[test]
+    fn torus_hit() {
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let torus = Torus::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 2.0, 0.5, mat);
+        // Across the hole, entering the tube at x = -2.5
+        let rec = torus.hit(Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0)), 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.5).abs() < 1e-9);
+        assert!((rec.normal.x() + 1.0).abs() < 1e-9);
+        // Down through the hole
+        assert!(torus.hit(Ray::new(Vec3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0)), 0.001, f64::INFINITY).is_none());
+    }
+}
@@ -0,0 +1,202 @@
+use std::sync::Arc;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::material::Scatter;
+use crate::hittable::{Hittable, HitRecord, Sphere};
+use crate::stats;
+
+const SDF_MAX_STEPS: u32 = 512;
+const SDF_EPS: f64 = 1e-4;          // Distance under which the surface is considered hit
+const SDF_MAX_DIST: f64 = 1000.0;   // Give up marching past this distance
+const SDF_NORMAL_EPS: f64 = 1e-5;   // Offset for the numerical gradient
+
+// Signed distance function tree, negative inside
+pub enum Sdf {
+    Sphere { center: Vec3, radius: f64 },
+    Box { center: Vec3, half_size: Vec3 },
+    // Around the Y axis
+    Torus { center: Vec3, major: f64, minor: f64 },
+    Capsule { a: Vec3, b: Vec3, radius: f64 },
+    Mandelbulb { center: Vec3, scale: f64, power: f64, iterations: u32 },
+    SmoothUnion(Box<Sdf>, Box<Sdf>, f64),
+    // First minus second
+    SmoothSubtract(Box<Sdf>, Box<Sdf>, f64),
+    // Infinite repetition with the given period, components of 0 are not repeated
+    Repeat(Box<Sdf>, Vec3),
+    // Rotation around the Y axis proportional to the height, in radians per unit
+    Twist(Box<Sdf>, f64),
+    // Move a tree, to place domain operations which act around the origin
+    Translate(Box<Sdf>, Vec3),
+}
+
+fn mix(a: f64, b: f64, h: f64) -> f64 {
+    a * (1.0 - h) + b * h
+}
+
+fn repeat_axis(x: f64, period: f64) -> f64 {
+    if period == 0.0 { x } else { x - period * f64::round(x / period) }
+}
+
+impl Sdf {
+    // Constructors, to build trees without the boxing noise
+    pub fn sphere(center: Vec3, radius: f64) -> Self {
+        Sdf::Sphere{center, radius}
+    }
+    pub fn cube(center: Vec3, half_size: Vec3) -> Self {
+        Sdf::Box{center, half_size}
+    }
+    pub fn torus(center: Vec3, major: f64, minor: f64) -> Self {
+        Sdf::Torus{center, major, minor}
+    }
+    pub fn capsule(a: Vec3, b: Vec3, radius: f64) -> Self {
+        Sdf::Capsule{a, b, radius}
+    }
+    pub fn mandelbulb(center: Vec3, scale: f64) -> Self {
+        Sdf::Mandelbulb{center, scale, power: 8.0, iterations: 12}
+    }
+    pub fn smooth_union(self, other: Sdf, k: f64) -> Self {
+        Sdf::SmoothUnion(Box::new(self), Box::new(other), k)
+    }
+    pub fn smooth_subtract(self, other: Sdf, k: f64) -> Self {
+        Sdf::SmoothSubtract(Box::new(self), Box::new(other), k)
+    }
+    pub fn repeat(self, period: Vec3) -> Self {
+        Sdf::Repeat(Box::new(self), period)
+    }
+    pub fn twist(self, amount: f64) -> Self {
+        Sdf::Twist(Box::new(self), amount)
+    }
+    pub fn translate(self, offset: Vec3) -> Self {
+        Sdf::Translate(Box::new(self), offset)
+    }
+
+    pub fn distance(&self, p: Vec3) -> f64 {
+        match self {
+            Sdf::Sphere{center, radius} => (p - *center).length() - radius,
+            Sdf::Box{center, half_size} => {
+                let d = p - *center;
+                let q = Vec3::new(d.x().abs() - half_size.x(), d.y().abs() - half_size.y(), d.z().abs() - half_size.z());
+                let outside = Vec3::new(q.x().max(0.0), q.y().max(0.0), q.z().max(0.0)).length();
+                outside + f64::min(q.x().max(q.y()).max(q.z()), 0.0)
+            }
+            Sdf::Torus{center, major, minor} => {
+                let d = p - *center;
+                let ring = f64::sqrt(d.x()*d.x() + d.z()*d.z()) - major;
+                f64::sqrt(ring*ring + d.y()*d.y()) - minor
+            }
+            Sdf::Capsule{a, b, radius} => {
+                let pa = p - *a;
+                let ba = *b - *a;
+                let h = f64::clamp(pa.dot(ba) / ba.dot(ba), 0.0, 1.0);
+                (pa - ba * h).length() - radius
+            }
+            Sdf::Mandelbulb{center, scale, power, iterations} => {
+                let c = (p - *center) / *scale;
+                let mut z = c;
+                let mut dr = 1.0;
+                let mut r = 0.0;
+                for _ in 0..*iterations {
+                    r = z.length();
+                    if r > 2.0 {
+                        break;
+                    }
+                    // Raise to the power in spherical coordinates
+                    let theta = f64::acos(z.z() / r) * power;
+                    let phi = f64::atan2(z.y(), z.x()) * power;
+                    dr = r.powf(power - 1.0) * power * dr + 1.0;
+                    let zr = r.powf(*power);
+                    z = Vec3::new(theta.sin() * phi.cos(), phi.sin() * theta.sin(), theta.cos()) * zr + c;
+                }
+                if r == 0.0 {
+                    return -*scale;
+                }
+                0.5 * r.ln() * r / dr * scale
+            }
+            Sdf::SmoothUnion(a, b, k) => {
+                let (da, db) = (a.distance(p), b.distance(p));
+                if *k <= 0.0 {
+                    return da.min(db);
+                }
+                let h = f64::clamp(0.5 + 0.5 * (db - da) / k, 0.0, 1.0);
+                mix(db, da, h) - k * h * (1.0 - h)
+            }
+            Sdf::SmoothSubtract(a, b, k) => {
+                let (da, db) = (a.distance(p), b.distance(p));
+                if *k <= 0.0 {
+                    return da.max(-db);
+                }
+                let h = f64::clamp(0.5 - 0.5 * (da + db) / k, 0.0, 1.0);
+                mix(da, -db, h) + k * h * (1.0 - h)
+            }
+            Sdf::Repeat(inner, period) => {
+                inner.distance(Vec3::new(repeat_axis(p.x(), period.x()),
+                                         repeat_axis(p.y(), period.y()),
+                                         repeat_axis(p.z(), period.z())))
+            }
+            Sdf::Twist(inner, amount) => {
+                let (s, c) = f64::sin_cos(amount * p.y());
+                let q = Vec3::new(c*p.x() - s*p.z(), p.y(), s*p.x() + c*p.z());
+                // The twist stretches space, scale down by its local Lipschitz bound
+                let stretch = amount * f64::sqrt(p.x()*p.x() + p.z()*p.z());
+                inner.distance(q) / f64::sqrt(1.0 + stretch*stretch)
+            }
+            Sdf::Translate(inner, offset) => inner.distance(p - *offset),
+        }
+    }
+
+    // Outward normal, from the gradient estimated with the tetrahedron technique
+    pub fn normal(&self, p: Vec3) -> Vec3 {
+        let h = SDF_NORMAL_EPS;
+        let k0 = Vec3::new(1.0, -1.0, -1.0);
+        let k1 = Vec3::new(-1.0, -1.0, 1.0);
+        let k2 = Vec3::new(-1.0, 1.0, -1.0);
+        let k3 = Vec3::new(1.0, 1.0, 1.0);
+        (k0 * self.distance(p + k0 * h) +
+         k1 * self.distance(p + k1 * h) +
+         k2 * self.distance(p + k2 * h) +
+         k3 * self.distance(p + k3 * h)).unit()
+    }
+}
+
+
+// Hittable rendering a distance field by sphere tracing
+pub struct SdfObject {
+    sdf: Sdf,
+    mat: Arc<dyn Scatter>,
+}
+
+impl SdfObject {
+    pub fn new(sdf: Sdf, mat: Arc<dyn Scatter>) -> Self {
+        SdfObject{sdf, mat}
+    }
+}
+
+impl Hittable for SdfObject {
+    fn hit(&self, r: Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+
+        stats::intersection_test();
+        // March along the unit direction, distances are then in world units
+        let len = r.direction().length();
+        let dir = r.direction() / len;
+        let mut t = t_min * len;
+        let t_end = f64::min(t_max * len, SDF_MAX_DIST);
+
+        // Rays starting inside, after a refraction, march towards the exit
+        let side = if self.sdf.distance(r.origin() + dir * t) < 0.0 { -1.0 } else { 1.0 };
+
+        for _ in 0..SDF_MAX_STEPS {
+            if t > t_end {
+                return None;
+            }
+            let p = r.origin() + dir * t;
+            let d = side * self.sdf.distance(p);
+            if d < SDF_EPS {
+                let normal = self.sdf.normal(p);
+                let (u, v) = Sphere::get_uv(normal);
+                return Some(HitRecord::new(r, t / len, normal, u, v, self.mat.clone()));
+            }
+            t += d;
+        }
+        None
+    }
+}
@@ -0,0 +1,40 @@
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::stats;
+
+// Ray / triangle intersection, Möller-Trumbore
+// Returns t and the barycentric coordinates of p1 and p2, both sides are hit
+pub fn intersect(r: Ray, p0: Vec3, p1: Vec3, p2: Vec3, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+    stats::intersection_test();
+    intersect_uncounted(r, p0, p1, p2, t_min, t_max)
+}
+
+// Same, for callers counting their own primitives rather than triangles
+pub fn intersect_uncounted(r: Ray, p0: Vec3, p1: Vec3, p2: Vec3, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+    let e1 = p1 - p0;
+    let e2 = p2 - p0;
+    let pvec = r.direction().cross(e2);
+    let det = e1.dot(pvec);
+    // Ray parallel to the triangle
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let tvec = r.origin() - p0;
+    let b1 = tvec.dot(pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+    let qvec = tvec.cross(e1);
+    let b2 = r.direction().dot(qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = e2.dot(qvec) * inv_det;
+    if t < t_min || t_max < t {
+        return None;
+    }
+    Some((t, b1, b2))
+}
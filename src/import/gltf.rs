@@ -0,0 +1,346 @@
+use std::collections::HashMap;
+use std::io;
+use std::sync::Arc;
+use crate::vec3::Vec3;
+use crate::color::Color;
+use crate::camera::Camera;
+use crate::hittable::World;
+use crate::hittable::mesh::Mesh;
+use crate::material::Scatter;
+use crate::material::principled::Principled;
+use crate::texture::{ImageTexture, srgb_to_linear};
+
+// Objects and camera imported from a glTF 2.0 file (.gltf or .glb)
+pub struct GltfScene {
+    pub objects: World,
+    pub camera: Option<Camera>,
+}
+
+// Column major 4x4 matrix, as stored by glTF
+type Mat4 = [[f64; 4]; 4];
+
+const IDENTITY: Mat4 = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+
+fn mat_mul(a: &Mat4, b: &Mat4) -> Mat4 {
+    let mut m = [[0.0; 4]; 4];
+    for (c, column) in m.iter_mut().enumerate() {
+        for (r, value) in column.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][r] * b[c][k]).sum();
+        }
+    }
+    m
+}
+
+fn column(m: &Mat4, c: usize) -> Vec3 {
+    Vec3::new(m[c][0], m[c][1], m[c][2])
+}
+
+fn transform_point(m: &Mat4, p: Vec3) -> Vec3 {
+    column(m, 0) * p.x() + column(m, 1) * p.y() + column(m, 2) * p.z() + column(m, 3)
+}
+
+fn transform_vector(m: &Mat4, v: Vec3) -> Vec3 {
+    column(m, 0) * v.x() + column(m, 1) * v.y() + column(m, 2) * v.z()
+}
+
+// Normals go through the inverse transpose, the cofactor matrix is proportional to it
+fn transform_normal(m: &Mat4, n: Vec3) -> Vec3 {
+    let (c0, c1, c2) = (column(m, 0), column(m, 1), column(m, 2));
+    let det = c0.dot(c1.cross(c2));
+    let n = c1.cross(c2) * n.x() + c2.cross(c0) * n.y() + c0.cross(c1) * n.z();
+    if det < 0.0 { -n.unit() } else { n.unit() }
+}
+
+// Load a glTF file, flattening its default scene's node hierarchy into world space
+// Cameras are mapped onto Camera::new with the aspect_ratio of the render, as the
+// image would be stretched otherwise
+pub fn load(filename: &str, aspect_ratio: f64) -> Result<GltfScene, gltf::Error> {
+    let (document, buffers, images) = gltf::import(filename)?;
+    build(&document, &buffers, &images, aspect_ratio)
+}
+
+fn invalid(message: &str) -> gltf::Error {
+    gltf::Error::Io(io::Error::new(io::ErrorKind::InvalidData, format!("glTF: {}", message)))
+}
+
+fn build(document: &gltf::Document, buffers: &[gltf::buffer::Data], images: &[gltf::image::Data], aspect_ratio: f64) -> Result<GltfScene, gltf::Error> {
+    let textures: Vec<ImageTexture> = images.iter().map(image_to_texture).collect();
+    let mut loader = Loader{buffers, textures: &textures, materials: HashMap::new(),
+                            scene: GltfScene{objects: World::new(), camera: None}, aspect_ratio};
+
+    if let Some(scene) = document.default_scene().or_else(|| document.scenes().next()) {
+        for node in scene.nodes() {
+            loader.load_node(&node, &IDENTITY)?;
+        }
+    }
+    Ok(loader.scene)
+}
+
+struct Loader<'a> {
+    buffers: &'a [gltf::buffer::Data],
+    textures: &'a [ImageTexture],
+    materials: HashMap<Option<usize>, Arc<dyn Scatter>>,
+    scene: GltfScene,
+    aspect_ratio: f64,
+}
+
+impl Loader<'_> {
+    fn load_node(&mut self, node: &gltf::Node, parent: &Mat4) -> Result<(), gltf::Error> {
+        let local = node.transform().matrix().map(|c| c.map(|v| v as f64));
+        let transform = mat_mul(parent, &local);
+
+        if let Some(mesh) = node.mesh() {
+            for primitive in mesh.primitives() {
+                if let Some(mesh) = self.load_primitive(&primitive, &transform)? {
+                    self.scene.objects.push(Box::new(mesh));
+                }
+            }
+        }
+
+        // First perspective camera wins, looking down its local -Z with +Y up
+        if let Some(camera) = node.camera() {
+            if let (None, gltf::camera::Projection::Perspective(p)) = (&self.scene.camera, camera.projection()) {
+                let lookfrom = transform_point(&transform, Vec3::new(0.0, 0.0, 0.0));
+                let forward = transform_vector(&transform, Vec3::new(0.0, 0.0, -1.0)).unit();
+                let vup = transform_vector(&transform, Vec3::new(0.0, 1.0, 0.0)).unit();
+                self.scene.camera = Some(Camera::new(lookfrom, lookfrom + forward, vup,
+                                                     (p.yfov() as f64).to_degrees(), self.aspect_ratio,
+                                                     0.0, // Pinhole
+                                                     1.0));
+            }
+        }
+
+        for child in node.children() {
+            self.load_node(&child, &transform)?;
+        }
+        Ok(())
+    }
+
+    fn load_primitive(&mut self, primitive: &gltf::Primitive, transform: &Mat4) -> Result<Option<Mesh>, gltf::Error> {
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            return Ok(None);
+        }
+        let reader = primitive.reader(|buffer| Some(&self.buffers[buffer.index()]));
+
+        let Some(positions) = reader.read_positions() else {
+            return Ok(None);
+        };
+        let positions: Vec<Vec3> = positions
+            .map(|p| transform_point(transform, Vec3::new(p[0] as f64, p[1] as f64, p[2] as f64)))
+            .collect();
+        let normals: Vec<Vec3> = reader.read_normals()
+            .map(|it| it.map(|n| transform_normal(transform, Vec3::new(n[0] as f64, n[1] as f64, n[2] as f64))).collect())
+            .unwrap_or_default();
+        // The UV set the base color texture reads, glTF UVs start at the top left
+        // of the image, ours at the bottom left
+        let tex_coord = primitive.material().pbr_metallic_roughness().base_color_texture().map(|info| info.tex_coord()).unwrap_or(0);
+        let uvs: Vec<(f64, f64)> = reader.read_tex_coords(tex_coord)
+            .map(|it| it.into_f32().map(|uv| (uv[0] as f64, 1.0 - uv[1] as f64)).collect())
+            .unwrap_or_default();
+        let colors: Vec<Color> = reader.read_colors(0)
+            .map(|it| it.into_rgb_f32().map(|c| Color::new(c[0] as f64, c[1] as f64, c[2] as f64)).collect())
+            .unwrap_or_default();
+        let indices: Vec<usize> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().map(|i| i as usize).collect(),
+            None => (0..positions.len()).collect(),
+        };
+        if indices.iter().any(|i| *i >= positions.len()) {
+            return Err(invalid("index out of range"));
+        }
+
+        // A mirroring transform flips the winding, restore it so faces still point outwards
+        let (c0, c1, c2) = (column(transform, 0), column(transform, 1), column(transform, 2));
+        let mirrored = c0.dot(c1.cross(c2)) < 0.0;
+        let triangles = indices.chunks_exact(3)
+            .map(|t| if mirrored { [t[0], t[2], t[1]] } else { [t[0], t[1], t[2]] })
+            .collect();
+
+        let mat = self.material(&primitive.material());
+        Ok(Some(Mesh::new(positions, normals, uvs, colors, triangles, mat)))
+    }
+
+    // Metallic-roughness materials map onto the principled material
+    fn material(&mut self, material: &gltf::Material) -> Arc<dyn Scatter> {
+        if let Some(mat) = self.materials.get(&material.index()) {
+            return mat.clone();
+        }
+
+        let pbr = material.pbr_metallic_roughness();
+        let [r, g, b, _a] = pbr.base_color_factor().map(|c| c as f64);
+        let base_color = Color::new(r, g, b);
+        let [er, eg, eb] = material.emissive_factor().map(|c| c as f64);
+        let emission = Color::new(er, eg, eb) * material.emissive_strength().unwrap_or(1.0) as f64;
+
+        let mut principled = match pbr.base_color_texture() {
+            // The base color factor multiplies the texture
+            Some(info) => Principled::textured(Arc::new(self.textures[info.texture().source().index()].tinted(base_color))),
+            None => Principled::new(base_color),
+        };
+        principled.metallic = pbr.metallic_factor() as f64;
+        principled.roughness = pbr.roughness_factor() as f64;
+        // glTF's factor of 1 is the plain reflectance of the ior, our 0.5
+        principled.specular = material.specular().map(|s| s.specular_factor() as f64 / 2.0).unwrap_or(0.5);
+        principled.transmission = material.transmission().map(|t| t.transmission_factor() as f64).unwrap_or(0.0);
+        principled.ior = material.ior().unwrap_or(1.5) as f64;
+        principled.emission = emission;
+        let mat: Arc<dyn Scatter> = Arc::new(principled);
+
+        self.materials.insert(material.index(), mat.clone());
+        mat
+    }
+}
+
+// Decode an sRGB image into a linear texture
+fn image_to_texture(data: &gltf::image::Data) -> ImageTexture {
+    use gltf::image::Format;
+    let (channels, bytes) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+
+    let channel = |pixel: &[u8], c: usize| -> f64 {
+        let c = usize::min(c, channels - 1);
+        let raw = &pixel[c * bytes..(c + 1) * bytes];
+        match bytes {
+            1 => srgb_to_linear(raw[0] as f64 / 255.0),
+            2 => srgb_to_linear(u16::from_le_bytes([raw[0], raw[1]]) as f64 / 65535.0),
+            // Float images are already linear
+            _ => f32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+        }
+    };
+
+    // Grayscale images have no green and blue, replicate the first channel
+    let pixels = data.pixels.chunks_exact(channels * bytes)
+        .map(|p| if channels < 3 {
+            let l = channel(p, 0);
+            Color::new(l, l, l)
+        } else {
+            Color::new(channel(p, 0), channel(p, 1), channel(p, 2))
+        })
+        .collect();
+    ImageTexture::new(data.width as usize, data.height as usize, pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ray::Ray;
+
+    // The triangle (0,0,0) (1,0,0) (0,1,0) as float positions, base64 encoded
+    const TRIANGLE: &str = "AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA";
+
+    // The triangle again, two UV sets, the second one (0,1) (1,1) (0,0), indices
+    // 0 1 2 or 0 1 5, and a white pixel PNG
+    const INDEXED: &str = "AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAgD8AAIA/AACAPwAAAAAAAAAAAAABAAIAAACJUE5HDQoaCgAAAA1JSERSAAAAAQAAAAEIAgAAAJB3U94AAAAMSURBVHicY/j//z8ABf4C/g3vRrgAAAAASUVORK5CYII=";
+    const OUT_OF_RANGE: &str = "AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAgD8AAIA/AACAPwAAAAAAAAAAAAABAAUAAACJUE5HDQoaCgAAAA1JSERSAAAAAQAAAAEIAgAAAJB3U94AAAAMSURBVHicY/j//z8ABf4C/g3vRrgAAAAASUVORK5CYII=";
+
+    // One triangle instanced by two nodes, the second one mirrored, and a camera
+    fn document() -> String {
+        format!(r#"{{
+            "asset": {{"version": "2.0"}},
+            "scene": 0,
+            "scenes": [{{"nodes": [0, 1, 2]}}],
+            "nodes": [
+                {{"mesh": 0, "translation": [0, 0, -2]}},
+                {{"mesh": 0, "translation": [0, 0, -4], "scale": [-1, 1, 1]}},
+                {{"camera": 0, "translation": [0, 0, 1]}}
+            ],
+            "cameras": [{{"type": "perspective", "perspective": {{"yfov": 0.8, "aspectRatio": 3.0, "znear": 0.1}}}}],
+            "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}, "material": 0}}]}}],
+            "materials": [{{"pbrMetallicRoughness": {{"baseColorFactor": [1, 0, 0, 1]}}}}],
+            "accessors": [{{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                            "min": [0, 0, 0], "max": [1, 1, 0]}}],
+            "bufferViews": [{{"buffer": 0, "byteLength": 36}}],
+            "buffers": [{{"byteLength": 36, "uri": "data:application/octet-stream;base64,{}"}}]
+        }}"#, TRIANGLE)
+    }
+
+    // One indexed triangle, textured through the second UV set
+    fn textured(buffer: &str) -> String {
+        format!(r#"{{
+            "asset": {{"version": "2.0"}},
+            "scenes": [{{"nodes": [0]}}],
+            "nodes": [{{"mesh": 0, "translation": [0, 0, -2]}}],
+            "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0, "TEXCOORD_0": 1, "TEXCOORD_1": 2}},
+                                          "indices": 3, "material": 0}}]}}],
+            "materials": [{{"pbrMetallicRoughness": {{"baseColorTexture": {{"index": 0, "texCoord": 1}}}}}}],
+            "textures": [{{"source": 0}}],
+            "images": [{{"bufferView": 4, "mimeType": "image/png"}}],
+            "accessors": [
+                {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0, 0, 0], "max": [1, 1, 0]}},
+                {{"bufferView": 1, "componentType": 5126, "count": 3, "type": "VEC2"}},
+                {{"bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC2"}},
+                {{"bufferView": 3, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+            ],
+            "bufferViews": [
+                {{"buffer": 0, "byteLength": 36}},
+                {{"buffer": 0, "byteOffset": 36, "byteLength": 24}},
+                {{"buffer": 0, "byteOffset": 60, "byteLength": 24}},
+                {{"buffer": 0, "byteOffset": 84, "byteLength": 6}},
+                {{"buffer": 0, "byteOffset": 92, "byteLength": 69}}
+            ],
+            "buffers": [{{"byteLength": 161, "uri": "data:application/octet-stream;base64,{}"}}]
+        }}"#, buffer)
+    }
+
+    fn import() -> GltfScene {
+        let (document, buffers, images) = gltf::import_slice(document()).unwrap();
+        build(&document, &buffers, &images, 1.5).unwrap()
+    }
+
+    #[test]
+    fn nodes_and_camera() {
+        let scene = import();
+        assert_eq!(scene.objects.len(), 2);
+        assert!(scene.camera.is_some());
+
+        let rec = scene.objects[0].hit(Ray::new(Vec3::new(0.25, 0.25, 0.0), Vec3::new(0.0, 0.0, -1.0)), 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-6);
+        assert!(rec.front_face);
+    }
+
+    #[test]
+    fn mirrored_winding() {
+        let scene = import();
+        // Mirrored about x, and still facing +z
+        let rec = scene.objects[1].hit(Ray::new(Vec3::new(-0.25, 0.25, 0.0), Vec3::new(0.0, 0.0, -1.0)), 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 4.0).abs() < 1e-6);
+        assert!(rec.front_face);
+        assert!(scene.objects[1].hit(Ray::new(Vec3::new(0.25, 0.25, 0.0), Vec3::new(0.0, 0.0, -1.0)), 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn render_aspect_ratio() {
+        // The file asks for 3, the render is 1.5 wide
+        let camera = import().camera.unwrap();
+        let right = camera.get_pinhole_ray(1.0, 0.5).direction();
+        let top = camera.get_pinhole_ray(0.5, 1.0).direction();
+        assert!((right.x() / top.y() - 1.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn base_color_uv_set() {
+        let (document, buffers, images) = gltf::import_slice(textured(INDEXED)).unwrap();
+        let scene = build(&document, &buffers, &images, 1.5).unwrap();
+        let rec = scene.objects[0].hit(Ray::new(Vec3::new(0.25, 0.25, 0.0), Vec3::new(0.0, 0.0, -1.0)), 0.001, f64::INFINITY).unwrap();
+        // The first set is all zeros, which would be (0,1) once flipped
+        assert!((rec.u - 0.25).abs() < 1e-6 && (rec.v - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn index_out_of_range() {
+        let (document, buffers, images) = gltf::import_slice(textured(OUT_OF_RANGE)).unwrap();
+        match build(&document, &buffers, &images, 1.5) {
+            Err(gltf::Error::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+            _ => panic!("expected an InvalidData error"),
+        }
+    }
+}
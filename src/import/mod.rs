@@ -0,0 +1,4 @@
+// Loaders for scene and mesh files made by other tools
+pub mod gltf;
+pub mod ply;
+pub mod stl;
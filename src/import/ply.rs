@@ -0,0 +1,314 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::sync::Arc;
+use crate::vec3::Vec3;
+use crate::color::Color;
+use crate::hittable::mesh::Mesh;
+use crate::material::Scatter;
+use crate::texture::srgb_to_linear;
+
+// Stanford PLY polygon files, ASCII and binary in both byte orders
+// Vertices may carry normals (nx, ny, nz), colors (red, green, blue) and
+// UVs (u, v or s, t), faces are polygons which get fan triangulated.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Scalar {
+    I8, U8, I16, U16, I32, U32, F32, F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> io::Result<Scalar> {
+        match name {
+            "char" | "int8" => Ok(Scalar::I8),
+            "uchar" | "uint8" => Ok(Scalar::U8),
+            "short" | "int16" => Ok(Scalar::I16),
+            "ushort" | "uint16" => Ok(Scalar::U16),
+            "int" | "int32" => Ok(Scalar::I32),
+            "uint" | "uint32" => Ok(Scalar::U32),
+            "float" | "float32" => Ok(Scalar::F32),
+            "double" | "float64" => Ok(Scalar::F64),
+            _ => Err(invalid(&format!("unknown property type {}", name))),
+        }
+    }
+
+    fn size(&self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    // Integer colors are 0 to max, float colors 0 to 1
+    fn color_scale(&self) -> f64 {
+        match self {
+            Scalar::U8 | Scalar::I8 => 255.0,
+            Scalar::U16 | Scalar::I16 => 65535.0,
+            _ => 1.0,
+        }
+    }
+}
+
+struct Property {
+    name: String,
+    scalar: Scalar,
+    // Type of the item count, for list properties
+    list_count: Option<Scalar>,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("PLY: {}", message))
+}
+
+// Source of values, either whitespace separated text or raw bytes
+enum Values<R: BufRead> {
+    Ascii(std::vec::IntoIter<String>),
+    Binary(R, bool),
+}
+
+impl<R: BufRead> Values<R> {
+    fn next(&mut self, scalar: Scalar) -> io::Result<f64> {
+        match self {
+            Values::Ascii(tokens) => {
+                let token = tokens.next().ok_or_else(|| invalid("unexpected end of data"))?;
+                token.parse::<f64>().map_err(|_| invalid(&format!("bad number {}", token)))
+            }
+            Values::Binary(reader, big_endian) => {
+                let mut buf = [0u8; 8];
+                let bytes = &mut buf[..scalar.size()];
+                reader.read_exact(bytes)?;
+                if *big_endian {
+                    bytes.reverse();
+                }
+                Ok(match scalar {
+                    Scalar::I8 => buf[0] as i8 as f64,
+                    Scalar::U8 => buf[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes([buf[0], buf[1]]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([buf[0], buf[1]]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]) as f64,
+                    Scalar::F64 => f64::from_le_bytes(buf),
+                })
+            }
+        }
+    }
+}
+
+fn read_header<R: BufRead>(reader: &mut R) -> io::Result<(Format, Vec<Element>)> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.trim() != "ply" {
+        return Err(invalid("missing magic number"));
+    }
+
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("missing end_header"));
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", "ascii", _] => format = Some(Format::Ascii),
+            ["format", "binary_little_endian", _] => format = Some(Format::BinaryLittleEndian),
+            ["format", "binary_big_endian", _] => format = Some(Format::BinaryBigEndian),
+            ["element", name, count] => {
+                let count = count.parse().map_err(|_| invalid("bad element count"))?;
+                elements.push(Element{name: name.to_string(), count, properties: Vec::new()});
+            }
+            ["property", "list", count, item, name] => {
+                let element = elements.last_mut().ok_or_else(|| invalid("property outside of an element"))?;
+                element.properties.push(Property{name: name.to_string(), scalar: Scalar::parse(item)?, list_count: Some(Scalar::parse(count)?)});
+            }
+            ["property", scalar, name] => {
+                let element = elements.last_mut().ok_or_else(|| invalid("property outside of an element"))?;
+                element.properties.push(Property{name: name.to_string(), scalar: Scalar::parse(scalar)?, list_count: None});
+            }
+            ["end_header"] => break,
+            _ => {} // comment, obj_info, blank lines
+        }
+    }
+
+    let format = format.ok_or_else(|| invalid("missing format"))?;
+    Ok((format, elements))
+}
+
+// Load a PLY file as a mesh
+pub fn load(filename: &str, mat: Arc<dyn Scatter>) -> io::Result<Mesh> {
+    read(BufReader::new(File::open(filename)?), mat)
+}
+
+fn read<R: BufRead>(mut reader: R, mat: Arc<dyn Scatter>) -> io::Result<Mesh> {
+    let (format, elements) = read_header(&mut reader)?;
+
+    let mut values = match format {
+        Format::Ascii => {
+            let mut text = String::new();
+            reader.read_to_string(&mut text)?;
+            let tokens: Vec<String> = text.split_whitespace().map(|t| t.to_string()).collect();
+            Values::Ascii(tokens.into_iter())
+        }
+        Format::BinaryLittleEndian => Values::Binary(reader, false),
+        Format::BinaryBigEndian => Values::Binary(reader, true),
+    };
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut colors = Vec::new();
+    let mut triangles = Vec::new();
+
+    for element in &elements {
+        let find = |names: &[&str]| element.properties.iter().position(|p| names.contains(&p.name.as_str()));
+        let xyz = [find(&["x"]), find(&["y"]), find(&["z"])];
+        let nxyz = [find(&["nx"]), find(&["ny"]), find(&["nz"])];
+        let rgb = [find(&["red", "r"]), find(&["green", "g"]), find(&["blue", "b"])];
+        let uv = [find(&["u", "s", "texture_u", "texture_s"]), find(&["v", "t", "texture_v", "texture_t"])];
+        let indices = find(&["vertex_indices", "vertex_index"]);
+
+        for _ in 0..element.count {
+            // Scalars of this instance, and the list of vertex indices if any
+            let mut scalars = vec![0.0; element.properties.len()];
+            let mut polygon: Vec<usize> = Vec::new();
+            for (i, property) in element.properties.iter().enumerate() {
+                match property.list_count {
+                    Some(count_type) => {
+                        let count = values.next(count_type)? as usize;
+                        for _ in 0..count {
+                            let value = values.next(property.scalar)?;
+                            if Some(i) == indices {
+                                polygon.push(value as usize);
+                            }
+                        }
+                    }
+                    None => scalars[i] = values.next(property.scalar)?,
+                }
+            }
+
+            match element.name.as_str() {
+                "vertex" => {
+                    let get = |i: Option<usize>| i.map(|i| scalars[i]).unwrap_or(0.0);
+                    positions.push(Vec3::new(get(xyz[0]), get(xyz[1]), get(xyz[2])));
+                    if nxyz.iter().all(|i| i.is_some()) {
+                        normals.push(Vec3::new(get(nxyz[0]), get(nxyz[1]), get(nxyz[2])).unit());
+                    }
+                    if uv.iter().all(|i| i.is_some()) {
+                        uvs.push((get(uv[0]), get(uv[1])));
+                    }
+                    if rgb.iter().all(|i| i.is_some()) {
+                        let channel = |i: Option<usize>| {
+                            let i = i.unwrap();
+                            srgb_to_linear(scalars[i] / element.properties[i].scalar.color_scale())
+                        };
+                        colors.push(Color::new(channel(rgb[0]), channel(rgb[1]), channel(rgb[2])));
+                    }
+                }
+                "face" => {
+                    for k in 1..polygon.len().saturating_sub(1) {
+                        triangles.push([polygon[0], polygon[k], polygon[k + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    if triangles.iter().flatten().any(|i| *i >= positions.len()) {
+        return Err(invalid("face index out of range"));
+    }
+    Ok(Mesh::new(positions, normals, uvs, colors, triangles, mat))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use crate::ray::Ray;
+    use crate::hittable::Hittable;
+    use crate::material::Lambertian;
+
+    const HEADER: &str = "element vertex 4
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+";
+
+    // Unit square in the z = 0 plane, one red quad facing +z
+    const POSITIONS: [[f32; 3]; 4] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [0.0, 1.0, 0.0]];
+
+    fn mat() -> Arc<dyn Scatter> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    fn check_square(mesh: &Mesh) {
+        assert_eq!(mesh.triangle_count(), 2);
+        let bounds = mesh.bounds();
+        assert_eq!((bounds.min.x(), bounds.min.y(), bounds.max.x(), bounds.max.y()), (0.0, 0.0, 1.0, 1.0));
+
+        let rec = mesh.hit(Ray::new(Vec3::new(0.75, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0)), 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-9);
+        assert!((rec.normal.z() - 1.0).abs() < 1e-9);
+        let color = rec.color;
+        assert!((color.r - 1.0).abs() < 1e-9 && color.g == 0.0 && color.b == 0.0);
+    }
+
+    #[test]
+    fn ascii() {
+        let mut text = format!("ply\nformat ascii 1.0\ncomment square\n{}", HEADER);
+        for p in POSITIONS {
+            text += &format!("{} {} {} 0 0 1 255 0 0\n", p[0], p[1], p[2]);
+        }
+        text += "4 0 1 2 3\n";
+        check_square(&read(Cursor::new(text), mat()).unwrap());
+    }
+
+    #[test]
+    fn binary_little_endian() {
+        let mut data = format!("ply\nformat binary_little_endian 1.0\n{}", HEADER).into_bytes();
+        for p in POSITIONS {
+            for x in p.into_iter().chain([0.0, 0.0, 1.0]) {
+                data.extend(x.to_le_bytes());
+            }
+            data.extend([255, 0, 0]);
+        }
+        data.push(4);
+        for i in 0..4i32 {
+            data.extend(i.to_le_bytes());
+        }
+        check_square(&read(Cursor::new(data), mat()).unwrap());
+    }
+
+    #[test]
+    fn errors() {
+        assert!(read(Cursor::new("obj\n"), mat()).is_err());
+        assert!(read(Cursor::new("ply\nelement vertex 0\n"), mat()).is_err());
+        let text = "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\nproperty float z\n\
+                    element face 1\nproperty list uchar int vertex_indices\nend_header\n0 0 0\n3 0 1 2\n";
+        assert!(read(Cursor::new(text), mat()).is_err());
+    }
+}
@@ -0,0 +1,304 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::sync::Arc;
+use crate::vec3::Vec3;
+use crate::hittable::mesh::Mesh;
+use crate::material::Scatter;
+
+// STL files, ASCII or binary, as exported by CAD tools
+// STL stores every triangle on its own, welding merges the corners which share
+// a position so the mesh gets its connectivity back, needed for smooth normals.
+
+#[derive(Debug, Copy, Clone)]
+pub struct StlOptions {
+    // Merge vertices closer than this distance, in file units
+    pub weld_tolerance: Option<f64>,
+    // Smooth normals across edges whose faces differ by less than this angle, in degrees
+    pub smooth_angle: Option<f64>,
+    // Unit conversion applied to the file coordinates, like 0.001 for millimeters to meters
+    pub scale: f64,
+    // The file is Z up, as most CAD tools are, convert to our Y up
+    pub z_up: bool,
+    // Center the part on the Y axis and put it on the y = 0 ground
+    pub center: bool,
+}
+
+impl Default for StlOptions {
+    fn default() -> Self {
+        StlOptions{weld_tolerance: Some(1e-6), smooth_angle: Some(30.0), scale: 1.0, z_up: true, center: true}
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("STL: {}", message))
+}
+
+// Triangles as triplets of corner positions
+fn parse_binary(data: &[u8]) -> io::Result<Vec<[Vec3; 3]>> {
+    let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+    if data.len() < 84 + count * 50 {
+        return Err(invalid("truncated binary file"));
+    }
+    let float = |o: usize| f32::from_le_bytes([data[o], data[o+1], data[o+2], data[o+3]]) as f64;
+    let vertex = |o: usize| Vec3::new(float(o), float(o + 4), float(o + 8));
+
+    // Each record is a normal, three vertices and a 16 bits attribute
+    Ok((0..count).map(|i| {
+        let o = 84 + i * 50;
+        [vertex(o + 12), vertex(o + 24), vertex(o + 36)]
+    }).collect())
+}
+
+fn parse_ascii(text: &str) -> io::Result<Vec<[Vec3; 3]>> {
+    let mut triangles = Vec::new();
+    let mut corners = Vec::with_capacity(3);
+    let mut words = text.split_whitespace();
+    while let Some(word) = words.next() {
+        match word {
+            "vertex" => {
+                let mut coord = || -> io::Result<f64> {
+                    let w = words.next().ok_or_else(|| invalid("unexpected end of file"))?;
+                    w.parse().map_err(|_| invalid(&format!("bad number {}", w)))
+                };
+                corners.push(Vec3::new(coord()?, coord()?, coord()?));
+            }
+            "endfacet" => {
+                // Polygonal facets are fan triangulated
+                for k in 1..corners.len().saturating_sub(1) {
+                    triangles.push([corners[0], corners[k], corners[k + 1]]);
+                }
+                corners.clear();
+            }
+            _ => {}
+        }
+    }
+    Ok(triangles)
+}
+
+// Load a STL file as a mesh
+pub fn load(filename: &str, options: StlOptions, mat: Arc<dyn Scatter>) -> io::Result<Mesh> {
+    parse(&fs::read(filename)?, options, mat)
+}
+
+fn parse(data: &[u8], options: StlOptions, mat: Arc<dyn Scatter>) -> io::Result<Mesh> {
+
+    // Some binary files also start with "solid", trust the size of the binary layout first
+    let is_binary = data.len() >= 84 && {
+        let count = u32::from_le_bytes([data[80], data[81], data[82], data[83]]) as usize;
+        data.len() == 84 + count * 50 || !data.starts_with(b"solid")
+    };
+    let mut triangles = if is_binary {
+        parse_binary(data)?
+    } else {
+        parse_ascii(&String::from_utf8_lossy(data))?
+    };
+
+    // Units and orientation
+    for tri in triangles.iter_mut() {
+        for p in tri.iter_mut() {
+            if options.z_up {
+                *p = Vec3::new(p.x(), p.z(), -p.y());
+            }
+            *p = *p * options.scale;
+        }
+    }
+
+    if options.center && !triangles.is_empty() {
+        let points = triangles.iter().flatten();
+        let min = points.clone().fold(Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+                                      |m, p| Vec3::new(m.x().min(p.x()), m.y().min(p.y()), m.z().min(p.z())));
+        let max = points.fold(Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+                              |m, p| Vec3::new(m.x().max(p.x()), m.y().max(p.y()), m.z().max(p.z())));
+        let offset = Vec3::new(-(min.x() + max.x()) / 2.0, -min.y(), -(min.z() + max.z()) / 2.0);
+        for p in triangles.iter_mut().flatten() {
+            *p = *p + offset;
+        }
+    }
+
+    // Weld, smoothing needs the connectivity so it welds with a default tolerance
+    let tolerance = options.weld_tolerance.or(options.smooth_angle.map(|_| 1e-6));
+    let (positions, indices) = match tolerance {
+        Some(tolerance) => weld(&triangles, tolerance * options.scale),
+        None => (triangles.iter().flatten().cloned().collect(),
+                 (0..triangles.len()).map(|i| [3*i, 3*i + 1, 3*i + 2]).collect()),
+    };
+
+    match options.smooth_angle {
+        Some(angle) => {
+            let (positions, normals, indices) = smooth_normals(&positions, &indices, angle);
+            Ok(Mesh::new(positions, normals, Vec::new(), Vec::new(), indices, mat))
+        }
+        None => Ok(Mesh::new(positions, Vec::new(), Vec::new(), Vec::new(), indices, mat)),
+    }
+}
+
+// Merge corners falling in the same cell of a grid of the tolerance size
+// A tolerance of zero or less, or a zero scale, only merges identical corners.
+fn weld(triangles: &[[Vec3; 3]], tolerance: f64) -> (Vec<Vec3>, Vec<[usize; 3]>) {
+    let key = |p: Vec3| [p.x(), p.y(), p.z()].map(|c| if tolerance > 0.0 {
+        (c / tolerance).round() as i64 as u64
+    } else {
+        // Adding zero turns -0 into 0
+        (c + 0.0).to_bits()
+    });
+    let mut index: HashMap<[u64; 3], usize> = HashMap::new();
+    let mut positions = Vec::new();
+
+    let indices = triangles.iter().map(|tri| tri.map(|p| {
+        *index.entry(key(p)).or_insert_with(|| {
+            positions.push(p);
+            positions.len() - 1
+        })
+    }))
+    // Triangles collapsed by the welding are dropped
+    .filter(|t| t[0] != t[1] && t[1] != t[2] && t[2] != t[0])
+    .collect();
+    (positions, indices)
+}
+
+// Per corner normals, averaging the faces around the vertex which are within
+// the angle of the corner's own face, so hard edges stay sharp
+fn smooth_normals(positions: &[Vec3], indices: &[[usize; 3]], angle: f64) -> (Vec<Vec3>, Vec<Vec3>, Vec<[usize; 3]>) {
+    let cos_angle = angle.to_radians().cos();
+
+    // Area weighted face normals, and the faces around every vertex
+    let face_normals: Vec<Vec3> = indices.iter()
+        .map(|t| (positions[t[1]] - positions[t[0]]).cross(positions[t[2]] - positions[t[0]]))
+        .collect();
+    let mut faces_of: Vec<Vec<usize>> = vec![Vec::new(); positions.len()];
+    for (f, t) in indices.iter().enumerate() {
+        for v in t {
+            faces_of[*v].push(f);
+        }
+    }
+
+    // Corners sharing a vertex and a normal are merged again
+    let mut out_positions = Vec::new();
+    let mut out_normals: Vec<Vec3> = Vec::new();
+    let mut out_indices = Vec::with_capacity(indices.len());
+    let mut seen: HashMap<(usize, [u64; 3]), usize> = HashMap::new();
+
+    for (f, t) in indices.iter().enumerate() {
+        let own = face_normals[f].unit();
+        out_indices.push(t.map(|v| {
+            let sum = faces_of[v].iter()
+                .map(|g| face_normals[*g])
+                .filter(|n| n.unit().dot(own) >= cos_angle)
+                .fold(Vec3::new(0.0, 0.0, 0.0), |a, n| a + n);
+            let normal = if sum.near_zero() { own } else { sum.unit() };
+            let bits = [normal.x().to_bits(), normal.y().to_bits(), normal.z().to_bits()];
+            *seen.entry((v, bits)).or_insert_with(|| {
+                out_positions.push(positions[v]);
+                out_normals.push(normal);
+                out_positions.len() - 1
+            })
+        }));
+    }
+    (out_positions, out_normals, out_indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+
+    // Corners of a tetrahedron, and its faces wound outwards
+    const CORNERS: [[f32; 3]; 4] = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    const FACES: [[usize; 3]; 4] = [[0, 2, 1], [0, 1, 3], [0, 3, 2], [1, 2, 3]];
+
+    fn binary(header: &[u8]) -> Vec<u8> {
+        let mut data = vec![0u8; 80];
+        data[..header.len()].copy_from_slice(header);
+        data.extend((FACES.len() as u32).to_le_bytes());
+        for face in FACES {
+            // The normal is ignored
+            data.extend([0u8; 12]);
+            for c in face {
+                for x in CORNERS[c] {
+                    data.extend(x.to_le_bytes());
+                }
+            }
+            data.extend([0u8; 2]);
+        }
+        data
+    }
+
+    fn ascii() -> String {
+        let mut text = String::from("solid tetrahedron\n");
+        for face in FACES {
+            text += "facet normal 0 0 0\nouter loop\n";
+            for c in face {
+                text += &format!("vertex {} {} {}\n", CORNERS[c][0], CORNERS[c][1], CORNERS[c][2]);
+            }
+            text += "endloop\nendfacet\n";
+        }
+        text + "endsolid tetrahedron\n"
+    }
+
+    fn mat() -> Arc<dyn Scatter> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    #[test]
+    fn ascii_and_binary_agree() {
+        let from_ascii = parse_ascii(&ascii()).unwrap();
+        let from_binary = parse_binary(&binary(b"")).unwrap();
+        assert_eq!(from_ascii.len(), 4);
+        for (a, b) in from_ascii.iter().zip(&from_binary) {
+            for (p, q) in a.iter().zip(b) {
+                assert_eq!((p.x(), p.y(), p.z()), (q.x(), q.y(), q.z()));
+            }
+        }
+    }
+
+    #[test]
+    fn load_both_formats() {
+        let options = StlOptions{smooth_angle: None, ..StlOptions::default()};
+        // A binary header starting with solid is still binary, from its size
+        for data in [ascii().into_bytes(), binary(b""), binary(b"solid exported")] {
+            let mesh = parse(&data, options, mat()).unwrap();
+            assert_eq!(mesh.triangle_count(), 4);
+            let bounds = mesh.bounds();
+            // Z up turned to Y up, centered and on the ground
+            assert_eq!((bounds.min.y(), bounds.max.y()), (0.0, 1.0));
+            assert_eq!((bounds.min.x(), bounds.max.x()), (-0.5, 0.5));
+        }
+    }
+
+    #[test]
+    fn truncated_binary() {
+        let data = binary(b"");
+        assert!(parse_binary(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn weld_tolerance() {
+        let triangles = parse_binary(&binary(b"")).unwrap();
+        let (positions, indices) = weld(&triangles, 1e-6);
+        assert_eq!((positions.len(), indices.len()), (4, 4));
+
+        // Corners off by less than the tolerance merge, only identical ones without one
+        let near = [[Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0)],
+                    [Vec3::new(1e-9, 0.0, -0.0), Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0)]];
+        assert_eq!(weld(&near, 1e-6).0.len(), 4);
+        for tolerance in [0.0, -1.0] {
+            assert_eq!(weld(&near, tolerance).0.len(), 5);
+        }
+        let options = StlOptions{weld_tolerance: Some(0.0), scale: 0.0, ..StlOptions::default()};
+        assert!(parse(&binary(b""), options, mat()).is_ok());
+    }
+
+    #[test]
+    fn hard_edges() {
+        let triangles = parse_binary(&binary(b"")).unwrap();
+        let (positions, indices) = weld(&triangles, 1e-6);
+        // Every face of a tetrahedron is over 30 degrees from the others
+        let (split, normals, _) = smooth_normals(&positions, &indices, 30.0);
+        assert_eq!((split.len(), normals.len()), (12, 12));
+        // But they are all within 130 degrees
+        let (smooth, _, _) = smooth_normals(&positions, &indices, 130.0);
+        assert_eq!(smooth.len(), 4);
+    }
+}
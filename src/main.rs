@@ -3,6 +3,8 @@ use std::time::Instant;
 use std::io::{self, Write};
 use std::sync::Arc;
 use std::sync::mpsc;
+use std::sync::OnceLock;
+use std::f64::consts::PI;
 use threadpool::ThreadPool;
 
 extern crate term_size;
@@ -19,44 +21,108 @@ use crate::ray::Ray;
 
 mod hittable;
 use crate::hittable::Hittable;
+use crate::hittable::HitRecord;
 use crate::hittable::Sphere;
 use crate::hittable::World;
+use crate::hittable::quad::Plane;
+use crate::hittable::csg::Csg;
 
 mod material;
-use crate::material::{Lambertian, Metal, Dielectric};
+use crate::material::{Scatter, Lambertian, Metal, Dielectric, SODIUM_D};
+use crate::material::medium::{MediaStack, Segment};
+use crate::material::merl::Merl;
+use crate::material::catcher::ShadowCatcher;
+use crate::material::conductor::Conductor;
+use crate::material::microfacet::MicrofacetConductor;
 
 mod camera;
 use crate::camera::Camera;
 
+mod stats;
+use crate::stats::Stats;
+
+mod scene;
+
+mod texture;
+
+mod spectrum;
+use crate::spectrum::{Spectrum, Wavelengths};
+
+mod import;
+
+mod environment;
+use crate::environment::Environment;
+
+mod toon;
+use crate::toon::{Toon, Fragment};
+
 const ASPECT_RATIO: f64 = 4.0 / 3.0;
 const IMAGE_WIDTH:  u32 = 1600;
 const IMAGE_HEIGHT: u32 = ((IMAGE_WIDTH as f64)/ASPECT_RATIO) as u32;
-const MAX_DEPTH: u32 = 50;           // Maximum ray depth
+const MAX_DEPTH: u32 = 256;          // Maximum ray depth, safety net behind Russian roulette
+const SURFACE_EPS: f64 = 0.01;       // Hits closer than this to a ray's origin are ignored, against self intersection
+const VOLUME_EPS: f64 = 1e-6;        // Same for rays scattered inside a volume, where there is no surface to avoid
+const RR_MIN_DEPTH: u32 = 3;         // Bounces before Russian roulette may terminate a path
+const RR_MAX_SURVIVAL: f64 = 0.95;   // Upper bound of the survival probability
 const SAMPLES_PER_PIXEL: u32  = 100;
 const SCALE: f64    = 1.0 / (SAMPLES_PER_PIXEL as f64);
+const WRITE_REPORT: bool = true;     // Write render statistics as JSON next to the image
+const SPECTRAL: bool = false;        // Trace wavelengths instead of RGB, for dispersion
+const TOON: bool = false;            // Cel shading with ink outlines instead of path tracing, for diagrams
+const SCENE: Scene = Scene::Spheres; // Demo scene to render
+const ALPHA: bool = false;           // Write RGBA, with a transparent background and shadow catchers, for compositing
+const ENVIRONMENT_MAP: &str = "environment.hdr";  // Equirectangular HDR or EXR lighting the scene instead of the sky gradient, when the file is around
+const ENVIRONMENT_ROTATION: f64 = 0.0; // Degrees around the vertical axis
+const ENVIRONMENT_INTENSITY: f64 = 1.0;
+const MERL_BRDFS: [&str; 2] = ["brdfs/gold-metallic-paint.binary", "brdfs/chrome.binary"]; // Measured spheres, when the files are around
+
+// Only the one picked by SCENE gets constructed
+#[allow(dead_code)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Scene {
+    Spheres,    // Random small spheres around three big ones
+    Materials,  // One sphere per material
+    Shapes,     // Every primitive
+    Imported,   // Meshes and camera loaded from files
+}
+
+static ENVIRONMENT: OnceLock<Environment> = OnceLock::new();
 
 // Write our buffer to the disk in any fileformat based on the extension
-fn write_image(filename: &str, w: u32, h: u32, buffer: &mut [Color])  {
-    let mut buf = vec![0; buffer.len()*3];
+// With ALPHA the colors are straight, not premultiplied by the alphas.
+fn write_image(filename: &str, w: u32, h: u32, buffer: &mut [Color], alphas: &[f64])  {
+    let channels = if ALPHA { 4 } else { 3 };
+    let mut buf = vec![0; buffer.len()*channels];
     for i in 0..buffer.len()-3 {
-        buf[i*3] =     (f64::clamp(buffer[i].r(), 0.0, 0.999)*255.0) as u8;
-        buf[(i*3)+1] = (f64::clamp(buffer[i].g(), 0.0, 0.999)*255.0) as u8;
-        buf[(i*3)+2] = (f64::clamp(buffer[i].b(), 0.0, 0.999)*255.0) as u8;
+        buf[i*channels] =     (f64::clamp(buffer[i].r(), 0.0, 0.999)*255.0) as u8;
+        buf[(i*channels)+1] = (f64::clamp(buffer[i].g(), 0.0, 0.999)*255.0) as u8;
+        buf[(i*channels)+2] = (f64::clamp(buffer[i].b(), 0.0, 0.999)*255.0) as u8;
+        if ALPHA {
+            buf[(i*channels)+3] = (f64::clamp(alphas[i], 0.0, 1.0)*255.0).round() as u8;
+        }
     }
-    image::save_buffer(filename, &buf, w, h, image::ColorType::Rgb8).unwrap();
+    let color_type = if ALPHA { image::ColorType::Rgba8 } else { image::ColorType::Rgb8 };
+    image::save_buffer(filename, &buf, w, h, color_type).unwrap();
     println!("Saved {}", filename);
 }
 
-// Put pixel in our buffer
-fn put_pixel(buffer: &mut [Color], x: u32, y: u32, color: Color) {
-    let offset: usize = (x+((IMAGE_HEIGHT-1)-y)*IMAGE_WIDTH) as usize;
+// Offset of a pixel in our buffers, top row first
+fn pixel_offset(x: u32, y: u32) -> usize {
+    (x+((IMAGE_HEIGHT-1)-y)*IMAGE_WIDTH) as usize
+}
+
+// Put pixel in our buffers, from the sums of premultiplied colors and alphas
+fn put_pixel(buffer: &mut [Color], alphas: &mut [f64], x: u32, y: u32, color: Color, alpha: f64) {
+    let offset: usize = pixel_offset(x, y);
 
-    // get RGB values, scaling it by sample size, and gamma correct them
-    let r = f64::sqrt(SCALE * color.r);
-    let g = f64::sqrt(SCALE * color.g);
-    let b = f64::sqrt(SCALE * color.b);
+    // get RGB values, dividing out the alpha, and gamma correct them
+    let scale = if alpha > 0.0 { 1.0 / alpha } else { 0.0 };
+    let r = f64::sqrt(scale * color.r);
+    let g = f64::sqrt(scale * color.g);
+    let b = f64::sqrt(scale * color.b);
 
     buffer[offset]    = Color::new(r, g, b);
+    alphas[offset]    = SCALE * alpha;
 }
 
 fn print_progress(width: usize, progress: f64) {
@@ -69,33 +135,55 @@ fn print_progress(width: usize, progress: f64) {
     }
 }
 
-// Create world, which is a Hittable trait
-fn create_world(seed: u64) -> World {
+// Create world, which is a Hittable trait, and the camera of the scene file if any
+fn create_world(seed: u64) -> (World, Option<Camera>) {
+
+    // Ground only catching shadows to composite over a backplate, with the alpha output
+    let ground_color = Color::new(0.5, 0.5, 0.5);
+    let ground: Arc<dyn Scatter> = if ALPHA { Arc::new(ShadowCatcher::new(ground_color)) } else { Arc::new(Lambertian::new(ground_color)) };
+
+    match SCENE {
+        Scene::Spheres => (random_spheres(seed, ground), None),
+        Scene::Materials => (scene::materials(ground), None),
+        Scene::Shapes => (scene::shapes(ground), None),
+        Scene::Imported => scene::imported(ground, ASPECT_RATIO),
+    }
+}
+
+fn random_spheres(seed: u64, mat_ground: Arc<dyn Scatter>) -> World {
 
     fastrand::seed(seed);
     let mat_lambert = Arc::new(Lambertian::new(Color::new(0.1, 0.2, 0.5)));
-    let mat_ground  = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
-    let mat_metal   = Arc::new(Metal::new(Color::new(0.8, 0.6, 0.2), 0.0));
+    let mat_metal   = Arc::new(MicrofacetConductor::preset(Conductor::Gold, 0.05));
     let mat_glass   = Arc::new(Dielectric::new(1.5));
 
-    let mut world = World::new();
-
-    // Big sphere as the ground
-    world.push(Box::new(Sphere::new(Vec3::new(0.0, -1000.0, -0.0), 1000.0, mat_ground)));
-    // Blue sphere
-    world.push(Box::new(Sphere::new(Vec3::new(-4.0, 1.0, 0.0), 1.0, mat_lambert)));
-    // Metallic sphere
-    world.push(Box::new(Sphere::new(Vec3::new(4.0, 1.0, 0.0), 1.0, mat_metal)));
-    // Hollow glass sphere
-    world.push(Box::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, mat_glass.clone())));
-    world.push(Box::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), -0.95, mat_glass.clone())));
+    let mut world: World = vec![
+        // Ground plane
+        Box::new(Plane::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), mat_ground)),
+        // Blue sphere
+        Box::new(Sphere::new(Vec3::new(-4.0, 1.0, 0.0), 1.0, mat_lambert)),
+        // Gold sphere
+        Box::new(Sphere::new(Vec3::new(4.0, 1.0, 0.0), 1.0, mat_metal)),
+        // Hollow glass sphere
+        Box::new(Csg::difference(Box::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 1.0, mat_glass.clone())),
+                                 Box::new(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 0.95, mat_glass.clone())))),
+    ];
+
+    // Measured materials
+    let mut measured = Vec::new();
+    for (filename, center) in MERL_BRDFS.iter().zip([Vec3::new(-2.0, 0.5, 1.5), Vec3::new(2.0, 0.5, 1.5)]) {
+        if let Ok(brdf) = Merl::load(filename) {
+            world.push(Box::new(Sphere::new(center, 0.5, Arc::new(brdf))));
+            measured.push(center);
+        }
+    }
 
     // Small spheres on the ground
     for a in -11..11 {
         for b in -11..11 {
             let choose_mat: f64 = fastrand::f64();
             let center: Vec3 = Vec3::new(a as f64 + 0.9*fastrand::f64(), 0.2, b as f64 + 0.9*fastrand::f64());
-            if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 {
+            if (center - Vec3::new(4.0, 0.2, 0.0)).length() > 0.9 && measured.iter().all(|m| (center - *m).length() > 0.8) {
 
                 if choose_mat < 0.8 {
                     let sphere_material = Arc::new(Lambertian::new(Color::new(fastrand::f64(), fastrand::f64(), fastrand::f64())));
@@ -113,49 +201,281 @@ fn create_world(seed: u64) -> World {
     world
 }
 
+// How the ray to trace was produced
+#[derive(Debug, Copy, Clone)]
+struct Bounce {
+    // Closest hit distance, against self intersection
+    t_min: f64,
+    // Density of the direction when the environment was sampled at the same
+    // vertex, to weight the two strategies, 0 otherwise
+    pdf: f64,
+}
+
+const CAMERA_BOUNCE: Bounce = Bounce{t_min: SURFACE_EPS, pdf: 0.0};
+
 // Get the color of a ray, recursive
-fn ray_color(r: Ray, world: &World, depth: u32) -> Color {
+// throughput is the product of the attenuations along the path so far
+fn ray_color(r: Ray, world: &World, depth: u32, throughput: Color, media: &mut MediaStack, bounce: Bounce) -> Color {
 
     // Depth limit reached, return black and send no more rays
-    if depth <= 0 {
+    if depth == 0 {
+        stats::path_length(MAX_DEPTH as usize);
         return Color::new(0.0,0.0,0.0);
     }
 
+    let hit = world.hit(r, bounce.t_min, f64::INFINITY);
+    hit_color(r, hit, world, depth, throughput, media, bounce)
+}
+
+// Color of a ray whose closest hit is already known
+fn hit_color(r: Ray, hit: Option<HitRecord>, world: &World, depth: u32, throughput: Color, media: &mut MediaStack, bounce: Bounce) -> Color {
+
     // Hit, get scattering informations
-    if let Some(rec) = world.hit(r, 0.01, f64::INFINITY) {
-        if let Some((attenuation, scattered)) = rec.mat.scatter(r, &rec) {
-            attenuation * ray_color(scattered, world, depth - 1)
+    if let Some(rec) = hit {
+        // Scattered inside the medium the ray goes through, or reaching the surface
+        let (emitted, scatter) = match media.sample_segment(r, rec.t * r.direction().length(), SODIUM_D) {
+            Segment::Scattered(weight, scattered) => (Color::new(0.0, 0.0, 0.0), Some((weight, scattered, Bounce{t_min: VOLUME_EPS, pdf: 0.0}))),
+            Segment::Transmitted(transmittance) => {
+                stats::hit(rec.mat.name());
+                let direct = direct_light(world, &rec, media);
+                let scatter = media.scatter(r, &rec, None).map(|(attenuation, scattered)| (attenuation * transmittance, scattered, surface_bounce(&rec, scattered, direct.is_some())));
+                (rec.mat.emitted(&rec) * transmittance + direct.unwrap_or(Color::new(0.0, 0.0, 0.0)), scatter)
+            }
+        };
+        if let Some((mut attenuation, scattered, next_bounce)) = scatter {
+            let bounces = MAX_DEPTH - depth;
+            let mut throughput = throughput * attenuation;
+
+            // Russian roulette, kill dim paths with a probability based on their throughput,
+            // and boost the survivors so the estimator stays unbiased
+            if bounces >= RR_MIN_DEPTH {
+                let survival = f64::min(throughput.max_component(), RR_MAX_SURVIVAL);
+                if fastrand::f64() >= survival {
+                    stats::path_length(bounces as usize);
+                    return emitted;
+                }
+                attenuation = attenuation / survival;
+                throughput = throughput / survival;
+            }
+
+            stats::secondary_ray();
+            emitted + attenuation * ray_color(scattered, world, depth - 1, throughput, media, next_bounce)
         } else {
-            Color::new(0.0, 0.0, 0.0)
+            stats::path_length((MAX_DEPTH - depth) as usize);
+            emitted
         }
         // No hit, get sky color
     } else {
-        let unit_direction = r.direction().unit();
-        let t = 0.5 * (unit_direction.y() + 1.0);
-        Color::new(1.0, 1.0, 1.0)* (1.0 - t) +  Color::new(0.5, 0.7, 1.0) * t
+        stats::path_length((MAX_DEPTH - depth) as usize);
+        sky_color(r) * environment_weight(r, bounce)
     }
 }
 
+// Spectral counterpart of ray_color, radiance is carried at the path's wavelengths
+fn spectral_ray_color(r: Ray, world: &World, depth: u32, throughput: Spectrum, wavelengths: &mut Wavelengths, media: &mut MediaStack, bounce: Bounce) -> Spectrum {
+
+    if depth == 0 {
+        stats::path_length(MAX_DEPTH as usize);
+        return Spectrum::constant(0.0);
+    }
+
+    let hit = world.hit(r, bounce.t_min, f64::INFINITY);
+    spectral_hit_color(r, hit, world, depth, throughput, wavelengths, media, bounce)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spectral_hit_color(r: Ray, hit: Option<HitRecord>, world: &World, depth: u32, throughput: Spectrum, wavelengths: &mut Wavelengths, media: &mut MediaStack, bounce: Bounce) -> Spectrum {
+
+    if let Some(rec) = hit {
+        let (emitted, scatter) = match media.sample_segment(r, rec.t * r.direction().length(), wavelengths.hero()) {
+            Segment::Scattered(weight, scattered) => (Color::new(0.0, 0.0, 0.0), Some((weight, scattered, Bounce{t_min: VOLUME_EPS, pdf: 0.0}))),
+            Segment::Transmitted(transmittance) => {
+                stats::hit(rec.mat.name());
+                // Wavelength dependent scattering only follows the hero wavelength
+                if rec.mat.dispersive() {
+                    wavelengths.terminate_secondary();
+                }
+                let direct = direct_light(world, &rec, media);
+                let scatter = media.scatter(r, &rec, Some(wavelengths.hero())).map(|(attenuation, scattered)| (attenuation * transmittance, scattered, surface_bounce(&rec, scattered, direct.is_some())));
+                (rec.mat.emitted(&rec) * transmittance + direct.unwrap_or(Color::new(0.0, 0.0, 0.0)), scatter)
+            }
+        };
+        let emitted = wavelengths.upsample(emitted);
+
+        if let Some((attenuation, scattered, next_bounce)) = scatter {
+            let bounces = MAX_DEPTH - depth;
+            let mut attenuation = wavelengths.upsample(attenuation);
+            let mut throughput = throughput * attenuation;
+
+            if bounces >= RR_MIN_DEPTH {
+                let survival = f64::min(throughput.max_component(), RR_MAX_SURVIVAL);
+                if fastrand::f64() >= survival {
+                    stats::path_length(bounces as usize);
+                    return emitted;
+                }
+                attenuation = attenuation / survival;
+                throughput = throughput / survival;
+            }
+
+            stats::secondary_ray();
+            emitted + attenuation * spectral_ray_color(scattered, world, depth - 1, throughput, wavelengths, media, next_bounce)
+        } else {
+            stats::path_length((MAX_DEPTH - depth) as usize);
+            emitted
+        }
+    } else {
+        stats::path_length((MAX_DEPTH - depth) as usize);
+        wavelengths.upsample(sky_color(r) * environment_weight(r, bounce))
+    }
+}
+
+// Light from the environment map at a diffuse hit, sampled by luminance and weighted
+// against the cosine bounce by the power heuristic, None when it is not sampled
+// Inside media the shadow ray would miss their absorption, bounces do it all there.
+fn direct_light(world: &World, rec: &HitRecord, media: &MediaStack) -> Option<Color> {
+    let environment = ENVIRONMENT.get()?;
+    if !media.is_empty() {
+        return None;
+    }
+    let albedo = rec.mat.diffuse_albedo(rec)?;
+
+    let (direction, pdf) = environment.sample(fastrand::f64(), fastrand::f64());
+    let cos = direction.dot(rec.mat.shading_normal(rec));
+    if pdf <= 0.0 || cos <= 0.0 || direction.dot(rec.geometric_normal) <= 0.0 {
+        return Some(Color::new(0.0, 0.0, 0.0));
+    }
+    stats::secondary_ray();
+    if world.hit(Ray::new(rec.p, direction), SURFACE_EPS, f64::INFINITY).is_some() {
+        return Some(Color::new(0.0, 0.0, 0.0));
+    }
+    let bsdf_pdf = cos / PI;
+    Some(environment.value(direction) * albedo * (bsdf_pdf / pdf * power_heuristic(pdf, bsdf_pdf)))
+}
+
+// Bounce off a surface, with the density of the cosine when the environment was sampled too
+fn surface_bounce(rec: &HitRecord, scattered: Ray, sampled_light: bool) -> Bounce {
+    let pdf = if sampled_light { f64::max(scattered.direction().unit().dot(rec.mat.shading_normal(rec)), 0.0) / PI } else { 0.0 };
+    Bounce{t_min: SURFACE_EPS, pdf}
+}
+
+// Weight of the environment reached by a bounce, its share with the light samples
+fn environment_weight(r: Ray, bounce: Bounce) -> f64 {
+    match ENVIRONMENT.get() {
+        Some(environment) if bounce.pdf > 0.0 => power_heuristic(bounce.pdf, environment.pdf(r.direction())),
+        _ => 1.0,
+    }
+}
+
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    pdf * pdf / (pdf * pdf + other_pdf * other_pdf)
+}
+
+// Environment map if one was loaded, else a gradient from white to blue
+fn sky_color(r: Ray) -> Color {
+    if let Some(environment) = ENVIRONMENT.get() {
+        return environment.value(r.direction());
+    }
+    let unit_direction = r.direction().unit();
+    let t = 0.5 * (unit_direction.y() + 1.0);
+    Color::new(1.0, 1.0, 1.0)* (1.0 - t) +  Color::new(0.5, 0.7, 1.0) * t
+}
+
 // Compute a pixel, using SAMPLES_PER_PIXEL samples
-fn compute_pixel(x: u32, y: u32, cam: Camera, world: &World) -> Color {
+// Returns the sums of the premultiplied colors and of the alphas, which are all
+// opaque unless ALPHA is on.
+fn compute_pixel(x: u32, y: u32, cam: Camera, world: &World) -> (Color, f64) {
 
-    let rng = fastrand::Rng::new();
+    let mut rng = fastrand::Rng::new();
     let mut pixel_color: Color = Color::new(0.0, 0.0, 0.0);
+    let mut pixel_alpha: f64 = 0.0;
 
     for _s in 0..SAMPLES_PER_PIXEL {
         let u = (x as f64 + rng.f64()) / (IMAGE_WIDTH-1) as f64;
         let v = (y as f64 + rng.f64()) / (IMAGE_HEIGHT-1) as f64;
         let r: Ray = cam.get_ray(u, v);
-        let color = ray_color(r, world, MAX_DEPTH);
+        stats::camera_ray();
+        stats::sample();
+        // Traced from the hit the ray was already tested against, so the path
+        // follows what was looked at
+        let mut trace = |r: Ray, hit: Option<HitRecord>| if SPECTRAL {
+            let mut wavelengths = Wavelengths::sample(rng.f64());
+            let radiance = spectral_hit_color(r, hit, world, MAX_DEPTH, Spectrum::constant(1.0), &mut wavelengths, &mut MediaStack::new(), CAMERA_BOUNCE);
+            wavelengths.rgb(radiance)
+        } else {
+            hit_color(r, hit, world, MAX_DEPTH, Color::new(1.0, 1.0, 1.0), &mut MediaStack::new(), CAMERA_BOUNCE)
+        };
+        let hit = world.hit(r, CAMERA_BOUNCE.t_min, f64::INFINITY);
+        let (color, alpha) = if !ALPHA {
+            (trace(r, hit), 1.0)
+        } else {
+            match hit {
+                None => (Color::new(0.0, 0.0, 0.0), 0.0),
+                Some(rec) => match rec.mat.shadow_catcher() {
+                    None => (trace(r, Some(rec)), 1.0),
+                    Some(reflectivity) => catch(r, &rec, reflectivity, world, &mut trace),
+                },
+            }
+        };
         pixel_color = pixel_color + color;
+        pixel_alpha += alpha;
+    }
+
+    (pixel_color, pixel_alpha)
+}
+
+// Premultiplied color and alpha of a shadow catcher seen from the camera
+// Reflections are the objects seen in the mirror direction, shadows the occluded
+// part of a cosine weighted sample of the sky, exact under a uniform sky.
+fn catch(r: Ray, rec: &HitRecord, reflectivity: f64, world: &World, trace: &mut impl FnMut(Ray, Option<HitRecord>) -> Color) -> (Color, f64) {
+    let is_object = |h: &HitRecord| h.mat.shadow_catcher().is_none();
+
+    let mut color = Color::new(0.0, 0.0, 0.0);
+    let mut alpha = 0.0;
+    if reflectivity > 0.0 {
+        let reflected = Ray::new(rec.p, r.direction().unit().reflect(rec.normal));
+        if let Some(h) = world.hit(reflected, SURFACE_EPS, f64::INFINITY).filter(is_object) {
+            color = trace(reflected, Some(h)) * reflectivity;
+            alpha = reflectivity;
+        }
     }
 
-    pixel_color
+    let mut direction = rec.normal + Vec3::random_unit_vector();
+    if direction.near_zero() {
+        direction = rec.normal;
+    }
+    stats::secondary_ray();
+    if world.hit(Ray::new(rec.p, direction), SURFACE_EPS, f64::INFINITY).as_ref().is_some_and(is_object) {
+        // Black, over what the reflection leaves of the backplate
+        alpha = 1.0;
+    }
+    (color, alpha)
+}
+
+// Compute a toon pixel, and what the outlines need to know about its center
+fn compute_toon_pixel(x: u32, y: u32, cam: Camera, world: &World, toon: &Toon) -> (Color, Fragment) {
+
+    let mut rng = fastrand::Rng::new();
+    let mut pixel_color: Color = Color::new(0.0, 0.0, 0.0);
+
+    for _s in 0..SAMPLES_PER_PIXEL {
+        let u = (x as f64 + rng.f64()) / (IMAGE_WIDTH-1) as f64;
+        let v = (y as f64 + rng.f64()) / (IMAGE_HEIGHT-1) as f64;
+        stats::camera_ray();
+        stats::sample();
+        pixel_color = pixel_color + toon.shade(cam.get_ray(u, v), world, sky_color);
+    }
+
+    let u = (x as f64 + 0.5) / (IMAGE_WIDTH-1) as f64;
+    let v = (y as f64 + 0.5) / (IMAGE_HEIGHT-1) as f64;
+    stats::camera_ray();
+    (pixel_color, toon.fragment(cam.get_pinhole_ray(u, v), world))
 }
 
 fn main() {
 
     let mut buffer: Vec<Color> = vec![Color{r: 0.0, g:0.0, b:0.0}; (IMAGE_WIDTH*IMAGE_HEIGHT) as usize];
+    let mut alphas: Vec<f64> = vec![1.0; (IMAGE_WIDTH*IMAGE_HEIGHT) as usize];
+    let mut fragments: Vec<Fragment> = vec![Fragment::background(); (IMAGE_WIDTH*IMAGE_HEIGHT) as usize];
 
 
     println!("Image {}x{}", IMAGE_WIDTH, IMAGE_HEIGHT);
@@ -176,11 +496,22 @@ fn main() {
 
     let seed: u64 = 7;
 
+    if let Ok(environment) = Environment::load(ENVIRONMENT_MAP, ENVIRONMENT_ROTATION, ENVIRONMENT_INTENSITY) {
+        println!("Environment {}", ENVIRONMENT_MAP);
+        let _ = ENVIRONMENT.set(environment);
+    }
+
+    // Key light of the toon mode, from the upper left of the default view
+    let toon = Toon::new(Vec3::new(1.0, 2.0, -0.5));
+
     let lookfrom: Vec3 = Vec3::new(10.0,2.0,10.0);
     let lookat: Vec3 = Vec3::new(0.0,0.0,0.0);
     let vup: Vec3 = Vec3::new(0.0,1.0,0.0);
 
 
+    let (world, scene_camera) = create_world(seed);
+    let world = Arc::new(world);
+
     let mut cam = Camera::new(lookfrom, lookat, vup, 20.0, ASPECT_RATIO,
                               0.1, // Aperture
                               15.0); // Dist to focus
@@ -198,51 +529,80 @@ fn main() {
 
     for i in start_image..end_image {
 
-        Ray::reset_count();
         let start_time = Instant::now();
 
         let cx = sx * f64::cos(angle.to_radians()) - sz*f64::sin(angle.to_radians());
         let cz = sx * f64::sin(angle.to_radians()) + sz*f64::cos(angle.to_radians());
-        cam.set_position(Vec3::new(cx, 2.0, cz));
+        match scene_camera {
+            Some(scene_camera) => cam = scene_camera,
+            None => cam.set_position(Vec3::new(cx, 2.0, cz)),
+        }
 
 
         let (tx, rx) = mpsc::channel();
+        let (stats_tx, stats_rx) = mpsc::channel();
 
         for y in (0..IMAGE_HEIGHT).rev() {
-            // TODO find a way to share a World so we don't create it for each line
-            let world = create_world(seed);
+            let world = world.clone();
 
             let tx2 = tx.clone();
+            let stats_tx2 = stats_tx.clone();
             pool.execute(move|| {
                 for x in 0..IMAGE_WIDTH {
-                    let pixel_color = compute_pixel(x, y, cam, &world);
+                    let (pixel_color, pixel_alpha, fragment) = if TOON {
+                        let (color, fragment) = compute_toon_pixel(x, y, cam, &world, &toon);
+                        (color, SAMPLES_PER_PIXEL as f64, fragment)
+                    } else {
+                        let (color, alpha) = compute_pixel(x, y, cam, &world);
+                        (color, alpha, Fragment::background())
+                    };
                     // Send pixel color to the mpsc channel
-                    tx2.send((x,y, pixel_color)).unwrap();
+                    tx2.send((x,y, pixel_color, pixel_alpha, fragment)).unwrap();
                 }
+                // Hand over this line's statistics
+                stats_tx2.send(stats::take()).unwrap();
             });
         }
         drop(tx);
+        drop(stats_tx);
         // Receive pixels color until we have them all
         // Channel will close the connection as soon as all the tx.clones are closed
         let mut pixel_count: u32 = 0;
         for received in &rx {
 
             // Get pixel position and color, and write it to our buffer
-            let (tx, ty, tc) = received;
-            put_pixel(&mut buffer, tx, ty, tc);
+            let (tx, ty, tc, ta, tf) = received;
+            put_pixel(&mut buffer, &mut alphas, tx, ty, tc, ta);
+            fragments[pixel_offset(tx, ty)] = tf;
 
             pixel_count+=1;
-            if (pixel_count%IMAGE_HEIGHT)==0 {
+            if pixel_count.is_multiple_of(IMAGE_HEIGHT) {
                 print_progress(term_w, (pixel_count+1) as f64 / (IMAGE_WIDTH*IMAGE_HEIGHT) as f64);
             }
         }
 
-        println!("");
+        println!();
+
+        if TOON {
+            toon.outline(&mut buffer, &fragments, IMAGE_WIDTH as usize);
+        }
 
         let elapsed_time = start_time.elapsed();
         println!("{}s", ((elapsed_time.as_secs()*1000)+elapsed_time.subsec_millis() as u64) as f64 / 1000.0);
-        println!("Ray count : {}", Ray::get_count());
-        write_image(&format!("test_{:04}.png", i).to_string(), IMAGE_WIDTH, IMAGE_HEIGHT, &mut buffer);
+
+        // Aggregate the statistics of every line
+        let mut render_stats = Stats::default();
+        for line_stats in &stats_rx {
+            render_stats.merge(&line_stats);
+        }
+        render_stats.print(elapsed_time);
+
+        write_image(&format!("test_{:04}.png", i).to_string(), IMAGE_WIDTH, IMAGE_HEIGHT, &mut buffer, &alphas);
+        if WRITE_REPORT {
+            let filename = format!("test_{:04}.json", i);
+            std::fs::write(&filename, render_stats.to_json(IMAGE_WIDTH, IMAGE_HEIGHT, elapsed_time)).unwrap();
+            println!("Saved {}", filename);
+        }
 
         angle+=angle_i;
     }
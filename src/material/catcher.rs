@@ -0,0 +1,45 @@
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::material::Scatter;
+
+// Stand in for the ground of a backplate photo, to composite renders over it
+// Seen from the camera with the alpha output on, only the shadows and reflections
+// of other objects show, everything else is left transparent. Other rays, which
+// bounce off objects towards it, see a plain diffuse surface of the given albedo.
+pub struct ShadowCatcher {
+    albedo: Color,
+    // Strength of the mirror reflections of objects, 0 for shadows only
+    reflectivity: f64,
+}
+
+impl ShadowCatcher {
+    pub fn new(albedo: Color) -> Self {
+        ShadowCatcher{albedo, reflectivity: 0.0}
+    }
+    // For glossy floors, the demo scenes keep to plain ones
+    #[allow(dead_code)]
+    pub fn reflective(albedo: Color, reflectivity: f64) -> Self {
+        ShadowCatcher{albedo, reflectivity: f64::clamp(reflectivity, 0.0, 1.0)}
+    }
+}
+
+impl Scatter for ShadowCatcher {
+    fn name(&self) -> &'static str {
+        "ShadowCatcher"
+    }
+    fn scatter(&self, _r_in: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let mut scatter_direction: Vec3 = rec.normal + Vec3::random_unit_vector();
+        if scatter_direction.near_zero() {
+            scatter_direction = rec.normal;
+        }
+        Some((self.albedo * rec.color, Ray::new(rec.p, scatter_direction)))
+    }
+    fn shadow_catcher(&self) -> Option<f64> {
+        Some(self.reflectivity)
+    }
+    fn diffuse_albedo(&self, rec: &HitRecord) -> Option<Color> {
+        Some(self.albedo * rec.color)
+    }
+}
@@ -0,0 +1,105 @@
+use crate::color::Color;
+
+// Library of common metals, by their complex index of refraction n + i k
+// Values are tabulated every 20 nm over the visible range, rounded from published
+// measurements (Johnson and Christy for the noble metals, Rakic for aluminium),
+// and linearly interpolated between the samples.
+
+const FIRST_WAVELENGTH: f64 = 380.0;
+const WAVELENGTH_STEP: f64 = 20.0;
+const SAMPLES: usize = 21;
+
+// Wavelengths of the red, green and blue the RGB mode uses
+const RGB_WAVELENGTHS: [f64; 3] = [630.0, 532.0, 465.0];
+
+// n then k, from FIRST_WAVELENGTH upwards
+type Table = ([f64; SAMPLES], [f64; SAMPLES]);
+
+const GOLD: Table = (
+    [1.462, 1.468, 1.456, 1.417, 1.349, 1.216, 0.972, 0.636, 0.489, 0.382, 0.299, 0.249, 0.205, 0.172, 0.140, 0.135, 0.131, 0.133, 0.137, 0.141, 0.147],
+    [1.929, 1.953, 1.954, 1.932, 1.885, 1.843, 1.873, 2.072, 2.339, 2.594, 2.838, 3.074, 3.304, 3.503, 3.702, 3.882, 4.063, 4.235, 4.406, 4.575, 4.741],
+);
+const SILVER: Table = (
+    [0.052, 0.050, 0.046, 0.040, 0.044, 0.050, 0.050, 0.050, 0.057, 0.057, 0.051, 0.055, 0.059, 0.055, 0.050, 0.045, 0.041, 0.037, 0.033, 0.031, 0.034],
+    [1.841, 2.104, 2.348, 2.553, 2.751, 2.947, 3.131, 3.315, 3.505, 3.679, 3.841, 4.010, 4.177, 4.332, 4.487, 4.645, 4.803, 4.960, 5.117, 5.272, 5.421],
+);
+const COPPER: Table = (
+    [1.229, 1.240, 1.244, 1.245, 1.227, 1.200, 1.170, 1.122, 1.065, 0.846, 0.505, 0.367, 0.269, 0.265, 0.260, 0.251, 0.242, 0.231, 0.219, 0.212, 0.221],
+    [2.143, 2.204, 2.255, 2.315, 2.385, 2.464, 2.521, 2.553, 2.555, 2.610, 2.706, 2.881, 3.064, 3.242, 3.419, 3.580, 3.741, 3.906, 4.072, 4.233, 4.375],
+);
+const ALUMINIUM: Table = (
+    [0.440, 0.490, 0.542, 0.594, 0.650, 0.710, 0.770, 0.846, 0.922, 1.008, 1.104, 1.200, 1.316, 1.432, 1.558, 1.694, 1.830, 2.058, 2.286, 2.480, 2.640],
+    [4.610, 4.860, 5.104, 5.348, 5.592, 5.836, 6.080, 6.324, 6.568, 6.804, 7.032, 7.260, 7.484, 7.708, 7.918, 8.114, 8.310, 8.434, 8.558, 8.586, 8.518],
+);
+const CHROME: Table = (
+    [1.200, 1.350, 1.442, 1.535, 1.627, 1.907, 2.250, 2.669, 3.050, 3.375, 3.700, 3.968, 4.236, 4.453, 4.619, 4.784, 4.950, 4.970, 4.990, 5.010, 5.030],
+    [3.200, 3.350, 3.473, 3.596, 3.719, 3.857, 4.000, 4.144, 4.308, 4.504, 4.700, 4.904, 5.108, 5.269, 5.386, 5.503, 5.620, 5.665, 5.710, 5.755, 5.800],
+);
+const IRON: Table = (
+    [2.200, 2.300, 2.400, 2.480, 2.560, 2.674, 2.800, 2.894, 2.948, 2.944, 2.940, 2.928, 2.916, 2.906, 2.897, 2.889, 2.880, 2.882, 2.885, 2.888, 2.890],
+    [2.500, 2.575, 2.650, 2.703, 2.757, 2.809, 2.860, 2.904, 2.943, 2.977, 3.010, 3.042, 3.074, 3.111, 3.154, 3.197, 3.240, 3.293, 3.345, 3.397, 3.450],
+);
+const TITANIUM: Table = (
+    [1.860, 1.955, 2.050, 2.148, 2.246, 2.326, 2.400, 2.487, 2.558, 2.604, 2.650, 2.686, 2.722, 2.757, 2.791, 2.826, 2.860, 2.902, 2.945, 2.987, 3.030],
+    [2.600, 2.700, 2.800, 2.907, 3.013, 3.126, 3.240, 3.359, 3.462, 3.541, 3.620, 3.696, 3.772, 3.841, 3.904, 3.967, 4.030, 4.093, 4.155, 4.218, 4.280],
+);
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Conductor {
+    Gold,
+    Silver,
+    Copper,
+    Aluminium,
+    Chrome,
+    Iron,
+    Titanium,
+}
+
+impl Conductor {
+    pub const ALL: [Conductor; 7] = [Conductor::Gold, Conductor::Silver, Conductor::Copper, Conductor::Aluminium,
+                                     Conductor::Chrome, Conductor::Iron, Conductor::Titanium];
+
+    // Lookup by name, as written in scene descriptions
+    pub fn from_name(name: &str) -> Option<Conductor> {
+        Conductor::ALL.iter().copied().find(|c| c.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Conductor::Gold => "gold",
+            Conductor::Silver => "silver",
+            Conductor::Copper => "copper",
+            Conductor::Aluminium => "aluminium",
+            Conductor::Chrome => "chrome",
+            Conductor::Iron => "iron",
+            Conductor::Titanium => "titanium",
+        }
+    }
+
+    fn table(&self) -> &'static Table {
+        match self {
+            Conductor::Gold => &GOLD,
+            Conductor::Silver => &SILVER,
+            Conductor::Copper => &COPPER,
+            Conductor::Aluminium => &ALUMINIUM,
+            Conductor::Chrome => &CHROME,
+            Conductor::Iron => &IRON,
+            Conductor::Titanium => &TITANIUM,
+        }
+    }
+
+    // n and k for red, green and blue
+    pub fn ior(&self) -> (Color, Color) {
+        let [r, g, b] = RGB_WAVELENGTHS.map(|w| self.ior_at(w));
+        (Color::new(r.0, g.0, b.0), Color::new(r.1, g.1, b.1))
+    }
+
+    // n and k at a wavelength in nanometers, held constant outside the table
+    pub fn ior_at(&self, wavelength: f64) -> (f64, f64) {
+        let (n, k) = self.table();
+        let x = f64::clamp((wavelength - FIRST_WAVELENGTH) / WAVELENGTH_STEP, 0.0, (SAMPLES - 1) as f64);
+        let i = usize::min(x as usize, SAMPLES - 2);
+        let t = x - i as f64;
+        (n[i] + (n[i + 1] - n[i]) * t, k[i] + (k[i + 1] - k[i]) * t)
+    }
+}
@@ -0,0 +1,67 @@
+use std::sync::Arc;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::material::Scatter;
+use crate::material::medium::Medium;
+use crate::texture::{Texture, SolidColor};
+
+// Partly transparent surfaces, for foliage, fences and decals
+// Rays go through with a probability of one minus the opacity, which World::hit
+// honours for every ray so transparent parts cast no shadows either.
+pub struct Cutout {
+    base: Arc<dyn Scatter>,
+    // Grayscale opacity, 1 is opaque
+    mask: Arc<dyn Texture>,
+}
+
+impl Cutout {
+    pub fn new(base: Arc<dyn Scatter>, opacity: f64) -> Self {
+        Cutout::textured(base, Arc::new(SolidColor::new(Color::new(opacity, opacity, opacity))))
+    }
+    // Image masks come from ImageTexture::load_alpha
+    pub fn textured(base: Arc<dyn Scatter>, mask: Arc<dyn Texture>) -> Self {
+        Cutout{base, mask}
+    }
+}
+
+impl Scatter for Cutout {
+    fn name(&self) -> &'static str {
+        self.base.name()
+    }
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        self.base.scatter(r_in, rec)
+    }
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.base.emitted(rec)
+    }
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+    fn scatter_wavelength(&self, r_in: Ray, rec: &HitRecord, wavelength: f64) -> Option<(Color, Ray)> {
+        self.base.scatter_wavelength(r_in, rec, wavelength)
+    }
+    fn medium(&self, wavelength: f64) -> Option<Medium> {
+        self.base.medium(wavelength)
+    }
+    fn scatter_interface(&self, r_in: Ray, rec: &HitRecord, eta_incident: f64, eta_transmitted: f64) -> Option<(Color, Ray)> {
+        self.base.scatter_interface(r_in, rec, eta_incident, eta_transmitted)
+    }
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        let m = self.mask.value(rec.u, rec.v, rec.p);
+        f64::clamp((m.r + m.g + m.b) / 3.0, 0.0, 1.0) * self.base.opacity(rec)
+    }
+    fn shadow_catcher(&self) -> Option<f64> {
+        self.base.shadow_catcher()
+    }
+    fn diffuse_albedo(&self, rec: &HitRecord) -> Option<Color> {
+        self.base.diffuse_albedo(rec)
+    }
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        self.base.shading_normal(rec)
+    }
+    fn base_color(&self, rec: &HitRecord) -> Color {
+        self.base.base_color(rec)
+    }
+}
@@ -0,0 +1,175 @@
+use std::sync::Arc;
+use crate::ray::Ray;
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::material::{Scatter, SODIUM_D};
+use crate::material::medium::Medium;
+use crate::material::microfacet::{Frame, Ggx, fresnel_dielectric};
+use crate::texture::{Texture, SolidColor};
+
+// Materials built out of other materials
+
+
+// Blend of two materials, picking one of them at every hit
+// The weight is the probability of the second one, constant or from a grayscale mask.
+pub struct Mix {
+    a: Arc<dyn Scatter>,
+    b: Arc<dyn Scatter>,
+    weight: Arc<dyn Texture>,
+}
+
+impl Mix {
+    pub fn new(a: Arc<dyn Scatter>, b: Arc<dyn Scatter>, weight: f64) -> Self {
+        Mix::textured(a, b, Arc::new(SolidColor::new(Color::new(weight, weight, weight))))
+    }
+    pub fn textured(a: Arc<dyn Scatter>, b: Arc<dyn Scatter>, mask: Arc<dyn Texture>) -> Self {
+        Mix{a, b, weight: mask}
+    }
+
+    fn weight(&self, rec: &HitRecord) -> f64 {
+        let w = self.weight.value(rec.u, rec.v, rec.p);
+        f64::clamp((w.r + w.g + w.b) / 3.0, 0.0, 1.0)
+    }
+
+    fn pick(&self, rec: &HitRecord) -> &Arc<dyn Scatter> {
+        if fastrand::f64() < self.weight(rec) { &self.b } else { &self.a }
+    }
+}
+
+impl Scatter for Mix {
+    fn name(&self) -> &'static str {
+        "Mix"
+    }
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        self.pick(rec).scatter(r_in, rec)
+    }
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        let w = self.weight(rec);
+        self.a.emitted(rec) * (1.0 - w) + self.b.emitted(rec) * w
+    }
+    fn dispersive(&self) -> bool {
+        self.a.dispersive() || self.b.dispersive()
+    }
+    fn scatter_wavelength(&self, r_in: Ray, rec: &HitRecord, wavelength: f64) -> Option<(Color, Ray)> {
+        self.pick(rec).scatter_wavelength(r_in, rec, wavelength)
+    }
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        let w = self.weight(rec);
+        self.a.opacity(rec) * (1.0 - w) + self.b.opacity(rec) * w
+    }
+    // A single volume is tracked, the first side's when both enclose one
+    fn medium(&self, wavelength: f64) -> Option<Medium> {
+        self.a.medium(wavelength).or_else(|| self.b.medium(wavelength))
+    }
+    // Sides without a medium reflect as usual, the others cross into the volume
+    fn scatter_interface(&self, r_in: Ray, rec: &HitRecord, eta_incident: f64, eta_transmitted: f64) -> Option<(Color, Ray)> {
+        let picked = self.pick(rec);
+        if picked.medium(SODIUM_D).is_some() {
+            picked.scatter_interface(r_in, rec, eta_incident, eta_transmitted)
+        } else {
+            picked.scatter(r_in, rec)
+        }
+    }
+    // A catcher only when both sides are, with their average reflectivity
+    fn shadow_catcher(&self) -> Option<f64> {
+        Some((self.a.shadow_catcher()? + self.b.shadow_catcher()?) / 2.0)
+    }
+    fn diffuse_albedo(&self, rec: &HitRecord) -> Option<Color> {
+        let w = self.weight(rec);
+        Some(self.a.diffuse_albedo(rec)? * (1.0 - w) + self.b.diffuse_albedo(rec)? * w)
+    }
+    fn base_color(&self, rec: &HitRecord) -> Color {
+        let w = self.weight(rec);
+        self.a.base_color(rec) * (1.0 - w) + self.b.base_color(rec) * w
+    }
+}
+
+
+// Clear or tinted dielectric coat over any base, for varnish and coated plastics
+// The coat reflects by Fresnel, otherwise light goes through it to the base and
+// back out, losing the coat's Fresnel reflection and absorption on the way.
+pub struct Layered {
+    base: Arc<dyn Scatter>,
+    ir: f64,
+    distribution: Ggx,
+    // Transmittance of a single pass through the coat at normal incidence
+    tint: Color,
+}
+
+impl Layered {
+    pub fn new(base: Arc<dyn Scatter>, index_of_refraction: f64, roughness: f64) -> Self {
+        Layered::tinted(base, index_of_refraction, roughness, Color::new(1.0, 1.0, 1.0))
+    }
+    pub fn tinted(base: Arc<dyn Scatter>, index_of_refraction: f64, roughness: f64, tint: Color) -> Self {
+        Layered{base, ir: index_of_refraction, distribution: Ggx::new(roughness, roughness), tint}
+    }
+
+    // Absorption crossing the coat at a cosine from the normal outside of it
+    fn absorption(&self, cos: f64) -> Color {
+        let cos_inside = f64::sqrt(1.0 - (1.0 - cos * cos) / (self.ir * self.ir));
+        let path = 1.0 / cos_inside;
+        Color::new(self.tint.r.powf(path), self.tint.g.powf(path), self.tint.b.powf(path))
+    }
+
+    // Scatter off the coat, or through it by the base's scattering
+    fn coat(&self, r_in: Ray, rec: &HitRecord, base: impl FnOnce() -> Option<(Color, Ray)>) -> Option<(Color, Ray)> {
+        let frame = Frame::new(rec.normal);
+        let wo = frame.to_local(-r_in.direction().unit());
+        if wo.z() <= 0.0 {
+            return base();
+        }
+
+        // Coat reflection, picked by Fresnel which then cancels out
+        let m = self.distribution.sample_visible_normal(wo);
+        if fastrand::f64() < fresnel_dielectric(wo.dot(m), self.ir) {
+            let wi = (-wo).reflect(m);
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            let weight = self.distribution.g2(wo, wi) / self.distribution.g1(wo);
+            return Some((Color::new(weight, weight, weight), Ray::new(rec.p, frame.to_world(wi))));
+        }
+
+        // Down to the base, the way in already paid its Fresnel by the choice above
+        let (attenuation, scattered) = base()?;
+        let cos_out = scattered.direction().unit().dot(rec.normal);
+        if cos_out <= 0.0 {
+            return Some((attenuation, scattered));
+        }
+        let exit = self.absorption(cos_out) * (1.0 - fresnel_dielectric(cos_out, self.ir));
+        Some((attenuation * self.absorption(wo.z()) * exit, scattered))
+    }
+}
+
+// Nothing of the base shows without going through the coat, not even as a shadow
+// catcher or a diffuse surface
+impl Scatter for Layered {
+    fn name(&self) -> &'static str {
+        "Layered"
+    }
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        self.coat(r_in, rec, || self.base.scatter(r_in, rec))
+    }
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+    fn scatter_wavelength(&self, r_in: Ray, rec: &HitRecord, wavelength: f64) -> Option<(Color, Ray)> {
+        self.coat(r_in, rec, || self.base.scatter_wavelength(r_in, rec, wavelength))
+    }
+    fn medium(&self, wavelength: f64) -> Option<Medium> {
+        self.base.medium(wavelength)
+    }
+    fn scatter_interface(&self, r_in: Ray, rec: &HitRecord, eta_incident: f64, eta_transmitted: f64) -> Option<(Color, Ray)> {
+        self.coat(r_in, rec, || self.base.scatter_interface(r_in, rec, eta_incident, eta_transmitted))
+    }
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.base.emitted(rec)
+    }
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        self.base.opacity(rec)
+    }
+    // Seen through the coat at normal incidence
+    fn base_color(&self, rec: &HitRecord) -> Color {
+        self.base.base_color(rec) * self.tint * self.tint
+    }
+}
@@ -0,0 +1,232 @@
+use std::sync::Arc;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::material::{Scatter, SODIUM_D};
+
+// Nested dielectrics, after Schmidt and Budge 2002
+// Every path keeps the stack of media it is inside. Where volumes overlap the
+// medium of highest priority wins, ties going to the last one entered, and the
+// surfaces of the others inside it are false interfaces the ray goes through.
+// Refraction at true interfaces uses the indices on both sides, so glass in
+// water bends light less than glass in air.
+
+// Volume enclosed by a material's surfaces
+#[derive(Debug, Copy, Clone)]
+pub struct Medium {
+    pub ior: f64,
+    // Beer-Lambert absorption coefficient per unit of distance
+    pub absorption: Color,
+    // Scattering coefficient per unit of distance, for translucent volumes
+    pub scattering: Color,
+    pub priority: u32,
+}
+
+const AIR_IOR: f64 = 1.0;
+
+// What happens to a ray on its way to the next surface
+pub enum Segment {
+    // Scattered inside the medium, with the weight of the event
+    Scattered(Color, Ray),
+    // Reached the surface, with the weight of the way there
+    Transmitted(Color),
+}
+
+pub struct MediaStack {
+    entries: Vec<Arc<dyn Scatter>>,
+}
+
+impl MediaStack {
+    pub fn new() -> Self {
+        MediaStack{entries: Vec::new()}
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn position(&self, mat: &Arc<dyn Scatter>) -> Option<usize> {
+        self.entries.iter().rposition(|m| std::ptr::addr_eq(Arc::as_ptr(m), Arc::as_ptr(mat)))
+    }
+
+    // Index of the medium we are in, the highest priority one
+    fn current(&self, wavelength: f64, skip: Option<usize>) -> Option<(usize, Medium)> {
+        let mut best: Option<(usize, Medium)> = None;
+        for (i, mat) in self.entries.iter().enumerate() {
+            if Some(i) == skip {
+                continue;
+            }
+            if let Some(medium) = mat.medium(wavelength) {
+                if best.is_none_or(|(_, b)| medium.priority >= b.priority) {
+                    best = Some((i, medium));
+                }
+            }
+        }
+        best
+    }
+
+    // Free flight through the medium we are in, towards a surface at the given distance
+    // Scattering media sample where the ray interacts, on a random channel, weighting
+    // by the average density of all three so colored media stay unbiased.
+    pub fn sample_segment(&self, r: Ray, distance: f64, wavelength: f64) -> Segment {
+        let medium = match self.current(wavelength, None) {
+            Some((_, medium)) => medium,
+            None => return Segment::Transmitted(Color::new(1.0, 1.0, 1.0)),
+        };
+        let extinction = medium.absorption + medium.scattering;
+        let transmittance = |d: f64| Color::new(f64::exp(-extinction.r * d), f64::exp(-extinction.g * d), f64::exp(-extinction.b * d));
+        let average = |c: Color| (c.r + c.g + c.b) / 3.0;
+        if medium.scattering.max_component() <= 0.0 {
+            return Segment::Transmitted(transmittance(distance));
+        }
+
+        let sigma = [extinction.r, extinction.g, extinction.b][fastrand::usize(0..3)];
+        let t = -f64::ln(1.0 - fastrand::f64()) / sigma;
+        if t < distance {
+            let tr = transmittance(t);
+            let weight = medium.scattering * tr / average(extinction * tr);
+            // Isotropic phase function
+            let p = r.origin() + r.direction().unit() * t;
+            Segment::Scattered(weight, Ray::new(p, Vec3::random_unit_vector()))
+        } else {
+            let tr = transmittance(distance);
+            Segment::Transmitted(tr / average(tr))
+        }
+    }
+
+    // Scatter at a hit, materials enclosing a medium go through the stack
+    // The wavelength is the hero's in the spectral mode, none in RGB
+    pub fn scatter(&mut self, r_in: Ray, rec: &HitRecord, wavelength: Option<f64>) -> Option<(Color, Ray)> {
+        let medium = match (rec.mat.medium(wavelength.unwrap_or(SODIUM_D)), wavelength) {
+            (Some(medium), _) => medium,
+            (None, Some(wavelength)) => return rec.mat.scatter_wavelength(r_in, rec, wavelength),
+            (None, None) => return rec.mat.scatter(r_in, rec),
+        };
+        let wavelength = wavelength.unwrap_or(SODIUM_D);
+        let current = self.current(wavelength, None);
+        let inside = self.position(&rec.mat);
+
+        // Indices on both sides, or none for a false interface
+        let interface = if rec.front_face {
+            match current {
+                Some((_, c)) if c.priority > medium.priority => None,
+                Some((_, c)) => Some((c.ior, medium.ior)),
+                None => Some((AIR_IOR, medium.ior)),
+            }
+        } else {
+            match (current, inside) {
+                (Some((i, _)), Some(j)) if i != j => None,
+                _ => {
+                    let outside = self.current(wavelength, inside).map(|(_, m)| m.ior).unwrap_or(AIR_IOR);
+                    Some((medium.ior, outside))
+                }
+            }
+        };
+
+        let (attenuation, scattered) = match interface {
+            Some((eta_incident, eta_transmitted)) => rec.mat.scatter_interface(r_in, rec, eta_incident, eta_transmitted)?,
+            None => (Color::new(1.0, 1.0, 1.0), Ray::new(rec.p, r_in.direction())),
+        };
+
+        // Crossing the surface enters or leaves the medium
+        if scattered.direction().dot(rec.normal) < 0.0 {
+            if rec.front_face {
+                self.entries.push(rec.mat.clone());
+            } else if let Some(j) = inside {
+                self.entries.remove(j);
+            }
+        }
+        Some((attenuation, scattered))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    // Clear volume recording the indices it is scattered between, and going straight through
+    struct Volume {
+        ior: f64,
+        priority: u32,
+        interfaces: Mutex<Vec<(f64, f64)>>,
+    }
+
+    impl Volume {
+        fn new(ior: f64, priority: u32) -> Arc<Self> {
+            Arc::new(Volume{ior, priority, interfaces: Mutex::new(Vec::new())})
+        }
+
+        fn interfaces(&self) -> Vec<(f64, f64)> {
+            self.interfaces.lock().unwrap().clone()
+        }
+    }
+
+    impl Scatter for Volume {
+        fn name(&self) -> &'static str {
+            "Volume"
+        }
+        fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+            Some((Color::new(1.0, 1.0, 1.0), Ray::new(rec.p, r_in.direction())))
+        }
+        fn medium(&self, _wavelength: f64) -> Option<Medium> {
+            Some(Medium{ior: self.ior, absorption: Color::new(0.0, 0.0, 0.0), scattering: Color::new(0.0, 0.0, 0.0), priority: self.priority})
+        }
+        fn scatter_interface(&self, r_in: Ray, rec: &HitRecord, eta_incident: f64, eta_transmitted: f64) -> Option<(Color, Ray)> {
+            self.interfaces.lock().unwrap().push((eta_incident, eta_transmitted));
+            self.scatter(r_in, rec)
+        }
+    }
+
+    // Goes down through the surface at height z, entering a volume with the outward normal up
+    fn cross(media: &mut MediaStack, mat: Arc<Volume>, z: f64, entering: bool) {
+        let r = Ray::new(Vec3::new(0.0, 0.0, z + 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let outward = Vec3::new(0.0, 0.0, if entering { 1.0 } else { -1.0 });
+        let rec = HitRecord::new(r, 1.0, outward, 0.0, 0.0, mat);
+        let (_, scattered) = media.scatter(r, &rec, None).unwrap();
+        assert_eq!(scattered.direction().z(), -1.0);
+    }
+
+    fn iors(media: &MediaStack) -> Vec<f64> {
+        media.entries.iter().map(|m| m.medium(SODIUM_D).unwrap().ior).collect()
+    }
+
+    #[test]
+    fn glass_in_water() {
+        let water = Volume::new(1.33, 1);
+        let glass = Volume::new(1.5, 2);
+        let mut media = MediaStack::new();
+
+        cross(&mut media, water.clone(), 3.0, true);
+        assert_eq!(iors(&media), [1.33]);
+        cross(&mut media, glass.clone(), 2.0, true);
+        assert_eq!(iors(&media), [1.33, 1.5]);
+        cross(&mut media, glass.clone(), 1.0, false);
+        assert_eq!(iors(&media), [1.33]);
+        cross(&mut media, water.clone(), 0.0, false);
+        assert!(media.is_empty());
+
+        assert_eq!(water.interfaces(), [(1.0, 1.33), (1.33, 1.0)]);
+        assert_eq!(glass.interfaces(), [(1.33, 1.5), (1.5, 1.33)]);
+    }
+
+    #[test]
+    fn false_interface() {
+        // Water of lower priority inside the glass is not seen by the ray
+        let glass = Volume::new(1.5, 2);
+        let water = Volume::new(1.33, 1);
+        let mut media = MediaStack::new();
+
+        cross(&mut media, glass.clone(), 3.0, true);
+        cross(&mut media, water.clone(), 2.0, true);
+        assert_eq!(iors(&media), [1.5, 1.33]);
+        cross(&mut media, water.clone(), 1.0, false);
+        assert_eq!(iors(&media), [1.5]);
+        cross(&mut media, glass.clone(), 0.0, false);
+        assert!(media.is_empty());
+
+        assert!(water.interfaces().is_empty());
+        assert_eq!(glass.interfaces(), [(1.0, 1.5), (1.5, 1.0)]);
+    }
+}
@@ -0,0 +1,164 @@
+use std::f64::consts::PI;
+use std::fs;
+use std::io;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::material::Scatter;
+use crate::material::microfacet::{Frame, Ggx};
+
+// Measured isotropic BRDFs of the MERL database (Matusik et al. 2003)
+// The files hold three int32 dimensions then the red, green and blue tables of
+// doubles, indexed by the half and difference angles of Rusinkiewicz.
+
+const THETA_H_BINS: usize = 90;
+const THETA_D_BINS: usize = 90;
+const PHI_D_BINS: usize = 180;
+const SIZE: usize = THETA_H_BINS * THETA_D_BINS * PHI_D_BINS;
+const SCALE: [f64; 3] = [1.0 / 1500.0, 1.15 / 1500.0, 1.66 / 1500.0];
+
+// Directions are sampled half from the cosine and half from a GGX lobe, which
+// catches the highlights of glossy measurements
+const SAMPLING_ROUGHNESS: f64 = 0.3;
+
+pub struct Merl {
+    // Scaled reflectances, channel after channel
+    data: Vec<f32>,
+    lobe: Ggx,
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("MERL: {}", message))
+}
+
+impl Merl {
+    pub fn load(filename: &str) -> io::Result<Self> {
+        Merl::parse(&fs::read(filename)?)
+    }
+
+    fn parse(bytes: &[u8]) -> io::Result<Self> {
+        if bytes.len() < 12 {
+            return Err(invalid("truncated header"));
+        }
+        let dim = |i: usize| i32::from_le_bytes([bytes[4*i], bytes[4*i + 1], bytes[4*i + 2], bytes[4*i + 3]]);
+        if (dim(0), dim(1), dim(2)) != (THETA_H_BINS as i32, THETA_D_BINS as i32, PHI_D_BINS as i32) {
+            return Err(invalid("unexpected dimensions"));
+        }
+        if bytes.len() < 12 + 3 * SIZE * 8 {
+            return Err(invalid("truncated data"));
+        }
+
+        let data = bytes[12..12 + 3 * SIZE * 8].chunks_exact(8).enumerate()
+            .map(|(i, b)| {
+                let v = f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]);
+                // Unmeasured entries are negative
+                (f64::max(v, 0.0) * SCALE[i / SIZE]) as f32
+            })
+            .collect();
+        Ok(Merl{data, lobe: Ggx::new(SAMPLING_ROUGHNESS, SAMPLING_ROUGHNESS)})
+    }
+
+    // BRDF value for two directions of the local shading frame
+    pub fn eval(&self, wi: Vec3, wo: Vec3) -> Color {
+        // Half vector, and incoming direction in the frame of the half vector
+        let h = (wi + wo).unit();
+        let theta_h = f64::acos(f64::clamp(h.z(), -1.0, 1.0));
+        let phi_h = f64::atan2(h.y(), h.x());
+        let (sin_p, cos_p) = (-phi_h).sin_cos();
+        let d = Vec3::new(wi.x() * cos_p - wi.y() * sin_p, wi.x() * sin_p + wi.y() * cos_p, wi.z());
+        let (sin_t, cos_t) = (-theta_h).sin_cos();
+        let d = Vec3::new(d.x() * cos_t + d.z() * sin_t, d.y(), -d.x() * sin_t + d.z() * cos_t);
+        let theta_d = f64::acos(f64::clamp(d.z(), -1.0, 1.0));
+        let mut phi_d = f64::atan2(d.y(), d.x());
+
+        // Reciprocity, the tables only cover half of the difference azimuths
+        if phi_d < 0.0 {
+            phi_d += PI;
+        }
+
+        // The half angle is stored with more resolution near the highlight
+        let index = |x: f64, bins: usize| usize::min(x.max(0.0) as usize, bins - 1);
+        let th = index(f64::sqrt(theta_h / (PI / 2.0)) * THETA_H_BINS as f64, THETA_H_BINS);
+        let td = index(theta_d / (PI / 2.0) * THETA_D_BINS as f64, THETA_D_BINS);
+        let pd = index(phi_d / PI * PHI_D_BINS as f64, PHI_D_BINS);
+        let i = pd + td * PHI_D_BINS + th * PHI_D_BINS * THETA_D_BINS;
+        Color::new(self.data[i] as f64, self.data[i + SIZE] as f64, self.data[i + 2 * SIZE] as f64)
+    }
+
+    // Density of the sampling mixture
+    fn pdf(&self, wi: Vec3, wo: Vec3) -> f64 {
+        let cosine = wi.z() / PI;
+        let m = (wi + wo).unit();
+        let glossy = self.lobe.g1(wo) * self.lobe.d(m) / (4.0 * wo.z());
+        0.5 * cosine + 0.5 * glossy
+    }
+}
+
+impl Scatter for Merl {
+    fn name(&self) -> &'static str {
+        "Merl"
+    }
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let frame = Frame::new(rec.normal);
+        let wo = frame.to_local(-r_in.direction().unit());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+
+        let wi = if fastrand::bool() {
+            let d = Vec3::new(0.0, 0.0, 1.0) + frame.to_local(Vec3::random_unit_vector());
+            if d.near_zero() { Vec3::new(0.0, 0.0, 1.0) } else { d.unit() }
+        } else {
+            (-wo).reflect(self.lobe.sample_visible_normal(wo))
+        };
+        if wi.z() <= 0.0 {
+            return None;
+        }
+
+        let weight = self.eval(wi, wo) * rec.color * (wi.z() / self.pdf(wi, wo));
+        Some((weight, Ray::new(rec.p, frame.to_world(wi))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(dims: [i32; 3]) -> Vec<u8> {
+        dims.iter().flat_map(|d| d.to_le_bytes()).collect()
+    }
+
+    // Constant tables, negative entries for the unmeasured ones
+    fn file(values: [f64; 3]) -> Vec<u8> {
+        let mut bytes = header([90, 90, 180]);
+        for v in values {
+            for _ in 0..SIZE {
+                bytes.extend(v.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    #[test]
+    fn header_and_size() {
+        assert!(Merl::parse(&[0; 8]).is_err());
+        assert!(Merl::parse(&header([90, 90, 360])).is_err());
+        let bytes = file([1.0, 1.0, 1.0]);
+        assert!(Merl::parse(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Merl::parse(&bytes).is_ok());
+    }
+
+    #[test]
+    fn scaled_values() {
+        let merl = Merl::parse(&file([1500.0, -1.0, 1500.0])).unwrap();
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let wi = Vec3::new(0.6, 0.0, 0.8);
+        for (wi, wo) in [(normal, normal), (wi, -wi.reflect(normal))] {
+            let c = merl.eval(wi, wo);
+            assert!((c.r - 1.0).abs() < 1e-6);
+            assert_eq!(c.g, 0.0);
+            assert!((c.b - 1.66).abs() < 1e-6);
+        }
+    }
+}
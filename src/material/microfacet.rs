@@ -0,0 +1,354 @@
+use std::f64::consts::PI;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::material::Scatter;
+use crate::material::conductor::Conductor;
+
+// GGX / Trowbridge-Reitz microfacet materials
+// Directions are handled in a local shading frame where the normal is +Z.
+// Sampling picks microfacet normals among the ones visible from the incoming
+// direction (Heitz 2018), so the weight reduces to Fresnel * G2 / G1.
+
+const MIN_ALPHA: f64 = 1e-3;    // Below this the distribution is too peaked to evaluate safely
+
+// Shading frame around a hit's normal
+pub struct Frame {
+    pub t: Vec3,
+    pub b: Vec3,
+    pub n: Vec3,
+}
+
+impl Frame {
+    pub fn new(n: Vec3) -> Self {
+        let (t, b) = Vec3::orthonormal_basis(n);
+        Frame{t, b, n}
+    }
+    // Frame following the surface parametrisation, the tangent turned by an angle
+    // in radians around the normal, for anisotropic materials
+    pub fn from_hit(rec: &HitRecord, rotation: f64) -> Self {
+        let (t, b) = rec.tangent_frame();
+        let (sin, cos) = rotation.sin_cos();
+        Frame{t: t * cos + b * sin, b: b * cos - t * sin, n: rec.normal}
+    }
+    pub fn to_local(&self, v: Vec3) -> Vec3 {
+        Vec3::new(v.dot(self.t), v.dot(self.b), v.dot(self.n))
+    }
+    pub fn to_world(&self, v: Vec3) -> Vec3 {
+        self.t * v.x() + self.b * v.y() + self.n * v.z()
+    }
+}
+
+// Anisotropic GGX distribution, alphas along the tangent and bitangent
+#[derive(Debug, Copy, Clone)]
+pub struct Ggx {
+    pub alpha_x: f64,
+    pub alpha_y: f64,
+}
+
+impl Ggx {
+    // Perceptual roughness in [0,1], squared into alphas
+    pub fn new(roughness_x: f64, roughness_y: f64) -> Self {
+        Ggx{alpha_x: f64::max(roughness_x * roughness_x, MIN_ALPHA),
+            alpha_y: f64::max(roughness_y * roughness_y, MIN_ALPHA)}
+    }
+
+    // Normal distribution function
+    pub fn d(&self, m: Vec3) -> f64 {
+        if m.z() <= 0.0 {
+            return 0.0;
+        }
+        let x = m.x() / self.alpha_x;
+        let y = m.y() / self.alpha_y;
+        let e = x*x + y*y + m.z()*m.z();
+        1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+    }
+
+    // Smith auxiliary function
+    pub fn lambda(&self, w: Vec3) -> f64 {
+        let z2 = w.z() * w.z();
+        if z2 == 0.0 {
+            return f64::INFINITY;
+        }
+        let a2 = (self.alpha_x * w.x()).powi(2) + (self.alpha_y * w.y()).powi(2);
+        (-1.0 + f64::sqrt(1.0 + a2 / z2)) / 2.0
+    }
+
+    // Masking
+    pub fn g1(&self, w: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Height correlated masking-shadowing
+    pub fn g2(&self, wo: Vec3, wi: Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Sample a microfacet normal visible from wo, which must be above the surface
+    pub fn sample_visible_normal(&self, wo: Vec3) -> Vec3 {
+        // Stretch to the hemisphere configuration
+        let vh = Vec3::new(self.alpha_x * wo.x(), self.alpha_y * wo.y(), wo.z()).unit();
+
+        // Orthonormal basis around it
+        let lensq = vh.x()*vh.x() + vh.y()*vh.y();
+        let t1 = if lensq > 0.0 { Vec3::new(-vh.y(), vh.x(), 0.0) / f64::sqrt(lensq) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let t2 = vh.cross(t1);
+
+        // Uniform disk, warped to the projected visible hemisphere
+        let r = f64::sqrt(fastrand::f64());
+        let phi = 2.0 * PI * fastrand::f64();
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * f64::sqrt(1.0 - p1*p1) + s * r * phi.sin();
+        let nh = t1 * p1 + t2 * p2 + vh * f64::sqrt(f64::max(0.0, 1.0 - p1*p1 - p2*p2));
+
+        // Unstretch
+        Vec3::new(self.alpha_x * nh.x(), self.alpha_y * nh.y(), f64::max(0.0, nh.z())).unit()
+    }
+}
+
+// Unpolarized Fresnel reflectance of a conductor of complex index eta + i k
+pub fn fresnel_conductor(cos_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_i * cos_i;
+    let sin2 = 1.0 - cos2;
+    let eta2 = eta * eta;
+    let k2 = k * k;
+
+    let t0 = eta2 - k2 - sin2;
+    let a2b2 = f64::sqrt(t0*t0 + 4.0*eta2*k2);
+    let t1 = a2b2 + cos2;
+    let a = f64::sqrt(f64::max(0.0, 0.5 * (a2b2 + t0)));
+    let t2 = 2.0 * cos_i * a;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos2 * a2b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    0.5 * (rp + rs)
+}
+
+pub fn fresnel_conductor_rgb(cos_i: f64, eta: Color, k: Color) -> Color {
+    Color::new(fresnel_conductor(cos_i, eta.r, k.r),
+               fresnel_conductor(cos_i, eta.g, k.g),
+               fresnel_conductor(cos_i, eta.b, k.b))
+}
+
+// Unpolarized Fresnel reflectance of a dielectric interface
+// eta is the index on the transmitted side over the incident side
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        // Total internal reflection
+        return 1.0;
+    }
+    let cos_t = f64::sqrt(1.0 - sin2_t);
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+// Refract w, pointing away from the surface on the side of n, eta as above
+pub fn refract(w: Vec3, n: Vec3, eta: f64) -> Option<Vec3> {
+    let cos_i = w.dot(n);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return None;
+    }
+    let cos_t = f64::sqrt(1.0 - sin2_t);
+    Some(-w / eta + n * (cos_i / eta - cos_t))
+}
+
+// Complex index from an artist friendly reflectance at normal incidence and
+// an edge tint (Gulbrandsen 2014), per channel
+pub fn conductor_from_reflectance(reflectance: Color, edge_tint: Color) -> (Color, Color) {
+    let channel = |r: f64, g: f64| {
+        let r = f64::clamp(r, 0.0, 0.99);
+        let sqrt_r = f64::sqrt(r);
+        let n = g * (1.0 - r) / (1.0 + r) + (1.0 - g) * (1.0 + sqrt_r) / (1.0 - sqrt_r);
+        let k = f64::sqrt(f64::max(0.0, (r * (n + 1.0).powi(2) - (n - 1.0).powi(2)) / (1.0 - r)));
+        (n, k)
+    };
+    let (nr, kr) = channel(reflectance.r, edge_tint.r);
+    let (ng, kg) = channel(reflectance.g, edge_tint.g);
+    let (nb, kb) = channel(reflectance.b, edge_tint.b);
+    (Color::new(nr, ng, nb), Color::new(kr, kg, kb))
+}
+
+
+// Rough metal
+pub struct MicrofacetConductor {
+    eta: Color,
+    k: Color,
+    distribution: Ggx,
+    // Measured metal, evaluated per wavelength in the spectral mode
+    preset: Option<Conductor>,
+    // Of the roughness axes from the surface tangent, in radians
+    rotation: f64,
+}
+
+impl MicrofacetConductor {
+    // Complex index of refraction per channel, and roughness along the tangent and bitangent
+    pub fn new(eta: Color, k: Color, roughness_x: f64, roughness_y: f64) -> Self {
+        MicrofacetConductor{eta, k, distribution: Ggx::new(roughness_x, roughness_y), preset: None, rotation: 0.0}
+    }
+    // Isotropic metal of the given color
+    pub fn from_color(color: Color, roughness: f64) -> Self {
+        let (eta, k) = conductor_from_reflectance(color, color);
+        MicrofacetConductor::new(eta, k, roughness, roughness)
+    }
+    // Metal of the library
+    pub fn preset(conductor: Conductor, roughness: f64) -> Self {
+        let (eta, k) = conductor.ior();
+        MicrofacetConductor{preset: Some(conductor), ..MicrofacetConductor::new(eta, k, roughness, roughness)}
+    }
+    // Turn the direction of anisotropy, in degrees from the surface tangent
+    pub fn rotated(self, degrees: f64) -> Self {
+        MicrofacetConductor{rotation: degrees.to_radians(), ..self}
+    }
+
+    // Reflected direction in the local frame, the microfacet normal, and the weight without Fresnel
+    fn sample(&self, wo: Vec3) -> Option<(Vec3, Vec3, f64)> {
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let m = self.distribution.sample_visible_normal(wo);
+        let wi = (-wo).reflect(m);
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        Some((wi, m, self.distribution.g2(wo, wi) / self.distribution.g1(wo)))
+    }
+}
+
+impl Scatter for MicrofacetConductor {
+    fn name(&self) -> &'static str {
+        "MicrofacetConductor"
+    }
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let frame = Frame::from_hit(rec, self.rotation);
+        let wo = frame.to_local(-r_in.direction().unit());
+        let (wi, m, weight) = self.sample(wo)?;
+        let fresnel = fresnel_conductor_rgb(wo.dot(m), self.eta, self.k);
+        Some((fresnel * weight, Ray::new(rec.p, frame.to_world(wi))))
+    }
+    fn dispersive(&self) -> bool {
+        self.preset.is_some()
+    }
+    fn scatter_wavelength(&self, r_in: Ray, rec: &HitRecord, wavelength: f64) -> Option<(Color, Ray)> {
+        let conductor = match self.preset {
+            Some(conductor) => conductor,
+            None => return self.scatter(r_in, rec),
+        };
+        let frame = Frame::from_hit(rec, self.rotation);
+        let wo = frame.to_local(-r_in.direction().unit());
+        let (wi, m, weight) = self.sample(wo)?;
+        let (eta, k) = conductor.ior_at(wavelength);
+        let fresnel = fresnel_conductor(wo.dot(m), eta, k) * weight;
+        Some((Color::new(fresnel, fresnel, fresnel), Ray::new(rec.p, frame.to_world(wi))))
+    }
+    // Reflectance at normal incidence
+    fn base_color(&self, _rec: &HitRecord) -> Color {
+        fresnel_conductor_rgb(1.0, self.eta, self.k)
+    }
+}
+
+
+// Frosted glass
+pub struct RoughDielectric {
+    ir: f64,
+    distribution: Ggx,
+}
+
+impl RoughDielectric {
+    pub fn new(index_of_refraction: f64, roughness: f64) -> Self {
+        RoughDielectric{ir: index_of_refraction, distribution: Ggx::new(roughness, roughness)}
+    }
+}
+
+impl Scatter for RoughDielectric {
+    fn name(&self) -> &'static str {
+        "RoughDielectric"
+    }
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let frame = Frame::from_hit(rec, 0.0);
+        let wo = frame.to_local(-r_in.direction().unit());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        // Index on the far side over the index on our side
+        let eta = if rec.front_face { self.ir } else { 1.0 / self.ir };
+
+        let m = self.distribution.sample_visible_normal(wo);
+        let reflectance = fresnel_dielectric(wo.dot(m), eta);
+
+        // Choose reflection or refraction by Fresnel, which then cancels out of the weight
+        let wi = if fastrand::f64() < reflectance {
+            let wi = (-wo).reflect(m);
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            wi
+        } else {
+            let wi = refract(wo, m, eta)?;
+            if wi.z() >= 0.0 {
+                return None;
+            }
+            wi
+        };
+
+        let weight = self.distribution.g2(wo, wi) / self.distribution.g1(wo);
+        Some((Color::new(weight, weight, weight), Ray::new(rec.p, frame.to_world(wi))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conductor_normal_incidence() {
+        for (n, k) in [(0.2, 3.0), (1.5, 0.0), (2.5, 4.2), (0.05, 0.5)] {
+            let expected = ((n - 1.0) * (n - 1.0) + k * k) / ((n + 1.0) * (n + 1.0) + k * k);
+            assert!((fresnel_conductor(1.0, n, k) - expected).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn dielectric_grazing_and_total_internal_reflection() {
+        // Towards 1 as the incidence grazes, from either side
+        for eta in [1.5, 1.0 / 1.5] {
+            let mut previous = fresnel_dielectric(1.0, eta);
+            for cos in [0.5, 0.2, 0.05, 1e-3, 1e-6] {
+                let f = fresnel_dielectric(cos, eta);
+                assert!(f >= previous);
+                previous = f;
+            }
+            assert!(previous > 0.999);
+        }
+        // From glass to air past the critical angle, 41.8 degrees
+        let critical = f64::sqrt(1.0 - 1.0 / (1.5 * 1.5));
+        assert!(fresnel_dielectric(critical + 1e-3, 1.0 / 1.5) < 1.0);
+        assert_eq!(fresnel_dielectric(critical - 1e-3, 1.0 / 1.5), 1.0);
+        assert!(refract(Vec3::new(f64::sqrt(1.0 - 0.5 * 0.5), 0.0, 0.5), Vec3::new(0.0, 0.0, 1.0), 1.0 / 1.5).is_none());
+    }
+
+    #[test]
+    fn white_furnace() {
+        // Without Fresnel the weight is all the energy reflected, lost only to
+        // the masking and shadowing of the microfacets
+        fastrand::seed(5);
+        let n = 20000;
+        for roughness in [0.05, 0.3, 0.6, 1.0] {
+            let conductor = MicrofacetConductor::new(Color::new(0.0, 0.0, 0.0), Color::new(0.0, 0.0, 0.0), roughness, roughness);
+            for cos in [0.9, 0.5, 0.2] {
+                let wo = Vec3::new(f64::sqrt(1.0 - cos * cos), 0.0, cos);
+                let mean = (0..n).map(|_| conductor.sample(wo).map(|(_, _, weight)| weight).unwrap_or(0.0)).sum::<f64>() / n as f64;
+                assert!(mean <= 1.0 + 1e-9, "roughness {} cos {}: {}", roughness, cos, mean);
+                if roughness < 0.1 {
+                    assert!(mean > 0.99, "roughness {} cos {}: {}", roughness, cos, mean);
+                }
+            }
+        }
+    }
+}
@@ -2,23 +2,87 @@ use crate::vec3::Vec3;
 use crate::ray::Ray;
 use crate::color::Color;
 use crate::hittable::HitRecord;
+use crate::texture::{Texture, SolidColor};
+use crate::material::medium::Medium;
+use std::sync::Arc;
+
+pub mod catcher;
+pub mod conductor;
+pub mod cutout;
+pub mod layered;
+pub mod medium;
+pub mod merl;
+pub mod microfacet;
+pub mod normal_map;
+pub mod principled;
+pub mod subsurface;
+pub mod thin;
 
 pub trait Scatter: Send + Sync {
     fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<(Color, Ray)>;
+    // Material type name, used by the render statistics
+    fn name(&self) -> &'static str;
+    // Light given off at the hit point, black for everything but emitters
+    fn emitted(&self, _rec: &HitRecord) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+    // Materials whose scattering depends on the wavelength, like dispersive glass,
+    // the spectral mode then calls scatter_wavelength with the hero wavelength
+    fn dispersive(&self) -> bool {
+        false
+    }
+    fn scatter_wavelength(&self, r_in: Ray, rec: &HitRecord, _wavelength: f64) -> Option<(Color, Ray)> {
+        self.scatter(r_in, rec)
+    }
+    // Volume enclosed by the surface, for nested dielectrics
+    fn medium(&self, _wavelength: f64) -> Option<Medium> {
+        None
+    }
+    // Scatter between media of the given indices, on the incident and on the far side
+    fn scatter_interface(&self, r_in: Ray, rec: &HitRecord, _eta_incident: f64, _eta_transmitted: f64) -> Option<(Color, Ray)> {
+        self.scatter(r_in, rec)
+    }
+    // Probability for a ray to stop at the hit rather than go through, for cutouts
+    fn opacity(&self, _rec: &HitRecord) -> f64 {
+        1.0
+    }
+    // Reflectivity of shadow catchers, None for every other material
+    fn shadow_catcher(&self) -> Option<f64> {
+        None
+    }
+    // Albedo of purely diffuse materials scattering along the cosine, for which
+    // the renderer can sample the lights directly
+    fn diffuse_albedo(&self, _rec: &HitRecord) -> Option<Color> {
+        None
+    }
+    // Normal the diffuse lobe is around, other than the hit's for perturbed normals
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        rec.normal
+    }
+    // Flat color of the surface, for the toon mode, white for clear materials
+    fn base_color(&self, rec: &HitRecord) -> Color {
+        self.diffuse_albedo(rec).unwrap_or(Color::new(1.0, 1.0, 1.0))
+    }
 }
 
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Clone)]
 pub struct Lambertian {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
 
 }
 impl Lambertian {
     pub fn new(albedo: Color) -> Self {
-        Lambertian{albedo: albedo}
+        Lambertian{albedo: Arc::new(SolidColor::new(albedo))}
+    }
+    pub fn textured(albedo: Arc<dyn Texture>) -> Self {
+        Lambertian{albedo}
     }
 }
 impl Scatter for Lambertian {
+    fn name(&self) -> &'static str {
+        "Lambertian"
+    }
     fn scatter(&self, _r_in: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
         let mut scatter_direction: Vec3 = rec.normal + Vec3::random_unit_vector();
         if scatter_direction.near_zero() {
@@ -26,7 +90,10 @@ impl Scatter for Lambertian {
             scatter_direction = rec.normal;
         }
         let scattered = Ray::new(rec.p, scatter_direction);
-        Some((self.albedo, scattered))
+        Some((self.albedo.value(rec.u, rec.v, rec.p) * rec.color, scattered))
+    }
+    fn diffuse_albedo(&self, rec: &HitRecord) -> Option<Color> {
+        Some(self.albedo.value(rec.u, rec.v, rec.p) * rec.color)
     }
 }
 
@@ -45,26 +112,86 @@ impl Metal {
     }
 }
 impl Scatter for Metal {
+    fn name(&self) -> &'static str {
+        "Metal"
+    }
     fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
         let reflected = r_in.direction().reflect(rec.normal).unit();
         let scattered = Ray::new(rec.p, reflected + self.fuzz*Vec3::random_in_unit_sphere());
 
         if scattered.direction().dot(rec.normal) > 0.0 {
-            Some((self.albedo, scattered))
+            Some((self.albedo * rec.color, scattered))
         } else {
             None
         }
     }
+    fn base_color(&self, rec: &HitRecord) -> Color {
+        self.albedo * rec.color
+    }
+}
+
+
+// Wavelength, in nanometers, at which indices of refraction are usually given,
+// used for dispersive glasses outside of the spectral mode
+pub const SODIUM_D: f64 = 589.3;
+
+// Index of refraction, constant or depending on the wavelength
+#[derive(Debug, Copy, Clone)]
+pub enum Ior {
+    Constant(f64),
+    // n = a + b / λ², λ in micrometers
+    Cauchy{a: f64, b: f64},
+    // n² = 1 + Σ b λ² / (λ² - c), λ in micrometers
+    Sellmeier{b: [f64; 3], c: [f64; 3]},
 }
 
+impl Ior {
+    // Common crown glass
+    pub const BK7: Ior = Ior::Sellmeier{b: [1.03961212, 0.231792344, 1.01046945], c: [0.00600069867, 0.0200179144, 103.560653]};
+    // Dense flint glass, strongly dispersive
+    pub const SF11: Ior = Ior::Sellmeier{b: [1.73759695, 0.313747346, 1.89878101], c: [0.013188707, 0.0623068142, 155.23629]};
 
+    // Index at a wavelength in nanometers
+    pub fn at(&self, wavelength: f64) -> f64 {
+        let l2 = (wavelength / 1000.0).powi(2);
+        match self {
+            Ior::Constant(n) => *n,
+            Ior::Cauchy{a, b} => a + b / l2,
+            Ior::Sellmeier{b, c} => f64::sqrt(1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>()),
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+}
+
+// Glass, optionally colored by absorption inside its volume (Beer-Lambert law)
 pub struct Dielectric {
-    ir: f64
+    ior: Ior,
+    // Absorption coefficient per unit of distance travelled inside
+    absorption: Color,
+    // Wins over lower priorities where volumes overlap
+    priority: u32,
 }
 
 impl Dielectric {
     pub fn new(index_of_refraction: f64) -> Self {
-        Dielectric{ir: index_of_refraction}
+        Dielectric::with_ior(Ior::Constant(index_of_refraction))
+    }
+    pub fn with_ior(ior: Ior) -> Self {
+        Dielectric::absorbing(ior, Color::new(0.0, 0.0, 0.0))
+    }
+    pub fn absorbing(ior: Ior, absorption: Color) -> Self {
+        Dielectric{ior, absorption, priority: 0}
+    }
+    // Glass letting through the transmittance color after the given distance
+    pub fn colored(ior: Ior, transmittance: Color, distance: f64) -> Self {
+        let coefficient = |t: f64| -f64::ln(f64::max(t, 1e-6)) / distance;
+        Dielectric::absorbing(ior, Color::new(coefficient(transmittance.r), coefficient(transmittance.g), coefficient(transmittance.b)))
+    }
+    pub fn prioritized(self, priority: u32) -> Self {
+        Dielectric{priority, ..self}
     }
     fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
         // Use Schlick's approximation for reflectance.
@@ -72,27 +199,71 @@ impl Dielectric {
         r0 = r0*r0;
         r0 + (1.0-r0)*f64::powf(1.0 - cosine, 5.0)
     }
-}
-
-impl Scatter for Dielectric {
-    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
-        let attenuation = Color::new(1.0, 1.0, 1.0);
-        let refraction_ratio = if rec.front_face { 1.0/self.ir } else { self.ir };
-
+    fn refract_or_reflect(r_in: Ray, rec: &HitRecord, refraction_ratio: f64) -> Ray {
         let unit_direction = r_in.direction().unit();
         let cos_theta = f64::min(-unit_direction.dot(rec.normal), 1.0);
         let sin_theta = f64::sqrt(1.0 - cos_theta*cos_theta);
 
         let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction;
-        if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > fastrand::f64() {
-            direction = Vec3::reflect(unit_direction, rec.normal);
+        let direction = if cannot_refract || Dielectric::reflectance(cos_theta, refraction_ratio) > fastrand::f64() {
+            Vec3::reflect(unit_direction, rec.normal)
         } else {
-            direction = Vec3::refract(unit_direction, rec.normal, refraction_ratio);
-        }
-        let scattered = Ray::new(rec.p, direction);
-        Some((attenuation, scattered))
+            Vec3::refract(unit_direction, rec.normal, refraction_ratio)
+        };
+        Ray::new(rec.p, direction)
     }
+}
 
+impl Scatter for Dielectric {
+    fn name(&self) -> &'static str {
+        "Dielectric"
+    }
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        self.scatter_wavelength(r_in, rec, SODIUM_D)
+    }
+    fn dispersive(&self) -> bool {
+        self.ior.is_dispersive()
+    }
+    fn scatter_wavelength(&self, r_in: Ray, rec: &HitRecord, wavelength: f64) -> Option<(Color, Ray)> {
+        // Hitting a back face, the ray travelled inside the glass since its last hit
+        let attenuation = if rec.front_face {
+            Color::new(1.0, 1.0, 1.0)
+        } else {
+            let distance = rec.t * r_in.direction().length();
+            Color::new(f64::exp(-self.absorption.r * distance),
+                       f64::exp(-self.absorption.g * distance),
+                       f64::exp(-self.absorption.b * distance))
+        };
+        let ir = self.ior.at(wavelength);
+        let refraction_ratio = if rec.front_face { 1.0/ir } else { ir };
+        Some((attenuation, Dielectric::refract_or_reflect(r_in, rec, refraction_ratio)))
+    }
+    fn medium(&self, wavelength: f64) -> Option<Medium> {
+        Some(Medium{ior: self.ior.at(wavelength), absorption: self.absorption, scattering: Color::new(0.0, 0.0, 0.0), priority: self.priority})
+    }
+    fn scatter_interface(&self, r_in: Ray, rec: &HitRecord, eta_incident: f64, eta_transmitted: f64) -> Option<(Color, Ray)> {
+        // Absorption is left to the media stack
+        Some((Color::new(1.0, 1.0, 1.0), Dielectric::refract_or_reflect(r_in, rec, eta_incident / eta_transmitted)))
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bk7_at_the_helium_d_line() {
+        assert!((Ior::BK7.at(587.6) - 1.5168).abs() < 1e-4);
+    }
+
+    #[test]
+    fn normal_dispersion() {
+        // Blue bends more than red
+        for ior in [Ior::Cauchy{a: 1.5046, b: 0.0042}, Ior::BK7, Ior::SF11] {
+            let n = [400.0, 450.0, 550.0, 650.0, 700.0].map(|l| ior.at(l));
+            assert!(n.windows(2).all(|w| w[0] > w[1]), "{:?}: {:?}", ior, n);
+        }
+        assert_eq!(Ior::Cauchy{a: 1.5046, b: 0.0042}.at(1000.0), 1.5046 + 0.0042);
+        assert!(!Ior::Constant(1.5).is_dispersive());
+    }
+}
@@ -0,0 +1,146 @@
+use std::sync::Arc;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::material::Scatter;
+use crate::material::medium::Medium;
+use crate::texture::Texture;
+
+// Surface detail without geometry, perturbing the shading normal of another material
+// The geometric normal is left alone, scattered rays which the shading normal
+// allows but which would cross the actual surface are dropped so no light leaks.
+
+const BUMP_DELTA: f64 = 1e-3;   // UV step of the bump map finite differences
+
+pub enum Perturbation {
+    // Tangent space normal map, RGB in [0,1] encoding a normal in [-1,1], blue up
+    Normal(Arc<dyn Texture>),
+    // Grayscale height map, scale is the height of white in UV units
+    Bump{height: Arc<dyn Texture>, scale: f64},
+}
+
+pub struct NormalMapped {
+    base: Arc<dyn Scatter>,
+    perturbation: Perturbation,
+}
+
+impl NormalMapped {
+    // Maps are data, load image ones with ImageTexture::load_linear
+    pub fn normal_map(base: Arc<dyn Scatter>, map: Arc<dyn Texture>) -> Self {
+        NormalMapped{base, perturbation: Perturbation::Normal(map)}
+    }
+    pub fn bump_map(base: Arc<dyn Scatter>, height: Arc<dyn Texture>, scale: f64) -> Self {
+        NormalMapped{base, perturbation: Perturbation::Bump{height, scale}}
+    }
+
+    // Hit record with the perturbed shading normal
+    fn shading(&self, rec: &HitRecord) -> HitRecord {
+        let (t, b) = rec.tangent_frame();
+        let n = rec.normal;
+        let normal = match &self.perturbation {
+            Perturbation::Normal(map) => {
+                let c = map.value(rec.u, rec.v, rec.p);
+                t * (2.0 * c.r - 1.0) + b * (2.0 * c.g - 1.0) + n * (2.0 * c.b - 1.0)
+            }
+            Perturbation::Bump{height, scale} => {
+                let h = |u: f64, v: f64| {
+                    let c = height.value(u, v, rec.p);
+                    (c.r + c.g + c.b) / 3.0
+                };
+                let h0 = h(rec.u, rec.v);
+                let dhdu = (h(rec.u + BUMP_DELTA, rec.v) - h0) / BUMP_DELTA;
+                let dhdv = (h(rec.u, rec.v + BUMP_DELTA) - h0) / BUMP_DELTA;
+                n - (t * dhdu + b * dhdv) * *scale
+            }
+        };
+
+        let mut shading = rec.clone();
+        // Degenerate or turned away normals keep the surface's
+        if !normal.near_zero() && normal.dot(rec.geometric_normal) > 0.0 {
+            shading.normal = normal.unit();
+        }
+        shading
+    }
+
+    fn consistent(rec: &HitRecord, shading: &HitRecord, scattered: Option<(Color, Ray)>) -> Option<(Color, Ray)> {
+        let (attenuation, ray) = scattered?;
+        let d = ray.direction();
+        if (d.dot(shading.normal) > 0.0) != (d.dot(rec.geometric_normal) > 0.0) {
+            return None;
+        }
+        Some((attenuation, ray))
+    }
+}
+
+impl Scatter for NormalMapped {
+    fn name(&self) -> &'static str {
+        self.base.name()
+    }
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let shading = self.shading(rec);
+        NormalMapped::consistent(rec, &shading, self.base.scatter(r_in, &shading))
+    }
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        self.base.emitted(rec)
+    }
+    fn opacity(&self, rec: &HitRecord) -> f64 {
+        self.base.opacity(rec)
+    }
+    fn dispersive(&self) -> bool {
+        self.base.dispersive()
+    }
+    fn scatter_wavelength(&self, r_in: Ray, rec: &HitRecord, wavelength: f64) -> Option<(Color, Ray)> {
+        let shading = self.shading(rec);
+        NormalMapped::consistent(rec, &shading, self.base.scatter_wavelength(r_in, &shading, wavelength))
+    }
+    fn medium(&self, wavelength: f64) -> Option<Medium> {
+        self.base.medium(wavelength)
+    }
+    fn scatter_interface(&self, r_in: Ray, rec: &HitRecord, eta_incident: f64, eta_transmitted: f64) -> Option<(Color, Ray)> {
+        let shading = self.shading(rec);
+        NormalMapped::consistent(rec, &shading, self.base.scatter_interface(r_in, &shading, eta_incident, eta_transmitted))
+    }
+    fn shadow_catcher(&self) -> Option<f64> {
+        self.base.shadow_catcher()
+    }
+    // The base sees the perturbed record for the direct light as for its bounces
+    fn diffuse_albedo(&self, rec: &HitRecord) -> Option<Color> {
+        self.base.diffuse_albedo(&self.shading(rec))
+    }
+    fn shading_normal(&self, rec: &HitRecord) -> Vec3 {
+        self.base.shading_normal(&self.shading(rec))
+    }
+    fn base_color(&self, rec: &HitRecord) -> Color {
+        self.base.base_color(rec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+
+    // Hit from above on a surface facing +z, with the tangent along x
+    fn hit(mat: Arc<dyn Scatter>) -> HitRecord {
+        let r = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::new(r, 1.0, Vec3::new(0.0, 0.0, 1.0), 0.5, 0.5, mat);
+        rec.tangent = Vec3::new(1.0, 0.0, 0.0);
+        rec
+    }
+
+    #[test]
+    fn diffuse_through_the_perturbed_normal() {
+        let albedo = Color::new(0.6, 0.5, 0.4);
+        // Tilted 45 degrees towards the tangent
+        let tilt = Vec3::new(1.0, 0.0, 1.0).unit();
+        let map = SolidColor::new(Color::new(0.5 + 0.5 * tilt.x(), 0.5, 0.5 + 0.5 * tilt.z()));
+        let mapped: Arc<dyn Scatter> = Arc::new(NormalMapped::normal_map(Arc::new(Lambertian::new(albedo)), Arc::new(map)));
+        let rec = hit(mapped.clone());
+
+        let diffuse = mapped.diffuse_albedo(&rec).unwrap();
+        assert!((diffuse.r - albedo.r).abs() < 1e-12 && (diffuse.g - albedo.g).abs() < 1e-12 && (diffuse.b - albedo.b).abs() < 1e-12);
+        assert!((mapped.shading_normal(&rec) - tilt).length() < 1e-9);
+    }
+}
@@ -0,0 +1,166 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::material::Scatter;
+use crate::material::microfacet::{Frame, Ggx, fresnel_dielectric, refract};
+use crate::texture::{Texture, SolidColor};
+
+// Principled uber material, after the Disney BRDF as found in DCC tools
+// Lobes are stacked as layers: a clearcoat over either a metal or a dielectric
+// base, the latter made of a specular reflection over a diffuse or transmissive
+// body. Every scatter event picks a single lobe with a probability matching its
+// share of the energy, so the returned weight is the lobe's own.
+//
+// Create one with new() and set the other parameters with the struct update syntax:
+//   Principled{metallic: 1.0, roughness: 0.2, ..Principled::new(color)}
+
+const CLEARCOAT_IOR: f64 = 1.5;
+
+pub struct Principled {
+    pub base_color: Arc<dyn Texture>,
+    // 0 dielectric, 1 metal
+    pub metallic: f64,
+    // Perceptual roughness of the specular, transmission and diffuse lobes
+    pub roughness: f64,
+    // Dielectric specular amount, 0.5 is the reflectance of the ior, 1 doubles it
+    pub specular: f64,
+    // Grazing angle velvet like reflection, for cloth
+    pub sheen: f64,
+    // Mix the sheen color from white to the base color
+    pub sheen_tint: f64,
+    // Colorless varnish layer over everything
+    pub clearcoat: f64,
+    pub clearcoat_roughness: f64,
+    // 0 opaque, 1 glass tinted by the base color
+    pub transmission: f64,
+    pub ior: f64,
+    // Emitted radiance, can go above 1
+    pub emission: Color,
+}
+
+impl Principled {
+    // Rough white plastic like defaults, with the given base color
+    pub fn new(base_color: Color) -> Self {
+        Principled::textured(Arc::new(SolidColor::new(base_color)))
+    }
+    pub fn textured(base_color: Arc<dyn Texture>) -> Self {
+        Principled{
+            base_color,
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_roughness: 0.03,
+            transmission: 0.0,
+            ior: 1.5,
+            emission: Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    // Reflect wo around a sampled visible microfacet normal, weight G2 / G1
+    fn sample_reflection(distribution: &Ggx, wo: Vec3) -> Option<(Vec3, Vec3, f64)> {
+        let m = distribution.sample_visible_normal(wo);
+        let wi = (-wo).reflect(m);
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        Some((wi, m, distribution.g2(wo, wi) / distribution.g1(wo)))
+    }
+}
+
+fn schlick_weight(cos: f64) -> f64 {
+    f64::powi(f64::clamp(1.0 - cos, 0.0, 1.0), 5)
+}
+
+impl Scatter for Principled {
+    fn name(&self) -> &'static str {
+        "Principled"
+    }
+
+    fn emitted(&self, rec: &HitRecord) -> Color {
+        if rec.front_face { self.emission } else { Color::new(0.0, 0.0, 0.0) }
+    }
+
+    fn base_color(&self, rec: &HitRecord) -> Color {
+        self.base_color.value(rec.u, rec.v, rec.p) * rec.color
+    }
+
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let frame = Frame::new(rec.normal);
+        let wo = frame.to_local(-r_in.direction().unit());
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let base_color = self.base_color.value(rec.u, rec.v, rec.p) * rec.color;
+        let white = Color::new(1.0, 1.0, 1.0);
+        let scattered = |wi: Vec3| Ray::new(rec.p, frame.to_world(wi));
+
+        // Leaving a transmissive body, only the glass interface applies
+        // Opaque surfaces are two sided
+        let inside = !rec.front_face && self.transmission > 0.0;
+
+        // Clearcoat
+        if !inside && self.clearcoat > 0.0 {
+            let coat = self.clearcoat * fresnel_dielectric(wo.z(), CLEARCOAT_IOR);
+            if fastrand::f64() < coat {
+                let distribution = Ggx::new(self.clearcoat_roughness, self.clearcoat_roughness);
+                let (wi, _, weight) = Principled::sample_reflection(&distribution, wo)?;
+                return Some((white * weight, scattered(wi)));
+            }
+        }
+
+        let distribution = Ggx::new(self.roughness, self.roughness);
+
+        // Metal, Schlick Fresnel tinted by the base color
+        if !inside && fastrand::f64() < self.metallic {
+            let (wi, m, weight) = Principled::sample_reflection(&distribution, wo)?;
+            let fresnel = base_color + (white - base_color) * schlick_weight(wo.dot(m));
+            return Some((fresnel * weight, scattered(wi)));
+        }
+
+        // Dielectric specular reflection
+        let m = distribution.sample_visible_normal(wo);
+        let eta = if inside { 1.0 / self.ior } else { self.ior };
+        let fresnel = fresnel_dielectric(wo.dot(m), eta);
+        let reflectance = if inside { fresnel } else { f64::min(1.0, fresnel * self.specular * 2.0) };
+        if fastrand::f64() < reflectance {
+            let wi = (-wo).reflect(m);
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            let weight = distribution.g2(wo, wi) / distribution.g1(wo);
+            return Some((white * weight, scattered(wi)));
+        }
+
+        // Rough glass, tinted by the base color on the way in
+        if inside || fastrand::f64() < self.transmission {
+            let wi = refract(wo, m, eta)?;
+            if wi.z() >= 0.0 {
+                return None;
+            }
+            let weight = distribution.g2(wo, wi) / distribution.g1(wo);
+            let tint = if inside { white } else { base_color };
+            return Some((tint * weight, scattered(wi)));
+        }
+
+        // Diffuse with Disney's retro-reflection, plus sheen
+        let mut direction = Vec3::new(0.0, 0.0, 1.0) + frame.to_local(Vec3::random_unit_vector());
+        if direction.near_zero() {
+            direction = Vec3::new(0.0, 0.0, 1.0);
+        }
+        let wi = direction.unit();
+        let h = (wi + wo).unit();
+        let cos_d = wi.dot(h);
+        let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+        let retro = (1.0 + (fd90 - 1.0) * schlick_weight(wi.z())) * (1.0 + (fd90 - 1.0) * schlick_weight(wo.z()));
+        let sheen_color = white * (1.0 - self.sheen_tint) + base_color * self.sheen_tint;
+        // Cosine sampling cancels the diffuse 1/pi, the sheen lobe has none
+        let weight = base_color * retro + sheen_color * (self.sheen * schlick_weight(cos_d) * PI);
+        Some((weight, scattered(wi)))
+    }
+}
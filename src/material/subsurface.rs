@@ -0,0 +1,43 @@
+use crate::ray::Ray;
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::material::{Scatter, Dielectric};
+use crate::material::medium::Medium;
+
+// Translucent material for skin, wax, marble or milk
+// The surface is a smooth dielectric boundary, and the volume inside scatters
+// light: the media stack makes paths random walk through it until they get out.
+pub struct Subsurface {
+    ir: f64,
+    absorption: Color,
+    scattering: Color,
+}
+
+impl Subsurface {
+    // Mean free path per channel, the average distance between two interactions
+    // in scene units, and the single scattering albedo, the chance for an
+    // interaction to scatter rather than absorb
+    pub fn new(index_of_refraction: f64, mean_free_path: Color, albedo: Color) -> Self {
+        let extinction = |mfp: f64| 1.0 / f64::max(mfp, 1e-6);
+        let extinction = Color::new(extinction(mean_free_path.r), extinction(mean_free_path.g), extinction(mean_free_path.b));
+        let scattering = extinction * albedo;
+        Subsurface{ir: index_of_refraction, absorption: extinction - scattering, scattering}
+    }
+}
+
+impl Scatter for Subsurface {
+    fn name(&self) -> &'static str {
+        "Subsurface"
+    }
+    // Without a media stack there is no walk, the boundary alone is left
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let refraction_ratio = if rec.front_face { 1.0 / self.ir } else { self.ir };
+        Some((Color::new(1.0, 1.0, 1.0), Dielectric::refract_or_reflect(r_in, rec, refraction_ratio)))
+    }
+    fn medium(&self, _wavelength: f64) -> Option<Medium> {
+        Some(Medium{ior: self.ir, absorption: self.absorption, scattering: self.scattering, priority: 0})
+    }
+    fn scatter_interface(&self, r_in: Ray, rec: &HitRecord, eta_incident: f64, eta_transmitted: f64) -> Option<(Color, Ray)> {
+        Some((Color::new(1.0, 1.0, 1.0), Dielectric::refract_or_reflect(r_in, rec, eta_incident / eta_transmitted)))
+    }
+}
@@ -0,0 +1,41 @@
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::color::Color;
+use crate::hittable::HitRecord;
+use crate::material::{Scatter, microfacet};
+
+// Infinitely thin glass slab, for window panes and soap bubbles
+// Light either reflects or goes straight through, the inter-reflections inside
+// the slab are summed into the reflectance.
+pub struct ThinDielectric {
+    ir: f64,
+}
+
+impl ThinDielectric {
+    pub fn new(index_of_refraction: f64) -> Self {
+        ThinDielectric{ir: index_of_refraction}
+    }
+}
+
+impl Scatter for ThinDielectric {
+    fn name(&self) -> &'static str {
+        "ThinDielectric"
+    }
+    fn scatter(&self, r_in: Ray, rec: &HitRecord) -> Option<(Color, Ray)> {
+        let unit_direction = r_in.direction().unit();
+        let cos_theta = f64::min(-unit_direction.dot(rec.normal), 1.0);
+
+        // Both faces are parallel, so there is no total internal reflection
+        let mut reflectance = microfacet::fresnel_dielectric(cos_theta, self.ir);
+        if reflectance < 1.0 {
+            reflectance += (1.0 - reflectance).powi(2) * reflectance / (1.0 - reflectance * reflectance);
+        }
+
+        let direction = if fastrand::f64() < reflectance {
+            Vec3::reflect(unit_direction, rec.normal)
+        } else {
+            unit_direction
+        };
+        Some((Color::new(1.0, 1.0, 1.0), Ray::new(rec.p, direction)))
+    }
+}
@@ -0,0 +1,192 @@
+use std::sync::Arc;
+use crate::vec3::Vec3;
+use crate::color::Color;
+use crate::camera::Camera;
+use crate::hittable::{World, Sphere};
+use crate::hittable::quad::{Plane, Quad, Cuboid};
+use crate::hittable::revolution::{Disk, Cylinder, Cone, Torus};
+use crate::hittable::csg::Csg;
+use crate::hittable::sdf::{Sdf, SdfObject};
+use crate::hittable::heightfield::Heightfield;
+use crate::material::{Scatter, Lambertian, Metal, Dielectric, Ior, SODIUM_D};
+use crate::material::thin::ThinDielectric;
+use crate::material::conductor::Conductor;
+use crate::material::cutout::Cutout;
+use crate::material::layered::{Mix, Layered};
+use crate::material::microfacet::{MicrofacetConductor, RoughDielectric};
+use crate::material::normal_map::NormalMapped;
+use crate::material::principled::Principled;
+use crate::material::subsurface::Subsurface;
+use crate::texture::ImageTexture;
+use crate::import::{gltf, ply, stl};
+
+// Demo scenes besides the random spheres, laid out around the origin for the
+// default camera. Files are used when they are around, with procedural stand ins
+// for the textures otherwise.
+
+const ALBEDO_TEXTURE: &str = "textures/albedo.png";
+const NORMAL_TEXTURE: &str = "textures/normal.png";
+const MASK_TEXTURE: &str = "textures/mask.png";
+const TERRAIN_TEXTURE: &str = "textures/terrain.png";
+const PLY_MODEL: &str = "models/model.ply";
+const STL_MODEL: &str = "models/model.stl";
+const GLTF_SCENE: &str = "models/scene.glb";
+
+// Two colors checkerboard, n squares a side
+fn checker(n: usize, a: Color, b: Color) -> ImageTexture {
+    let pixels = (0..n * n).map(|i| if (i / n + i % n).is_multiple_of(2) { a } else { b }).collect();
+    ImageTexture::new(n, n, pixels)
+}
+
+// Tangent space normal map of ripples along u
+fn ripples(n: usize) -> ImageTexture {
+    let pixels = (0..n * n).map(|i| {
+        let slope = 0.5 * f64::sin(2.0 * std::f64::consts::PI * 8.0 * (i % n) as f64 / n as f64);
+        let normal = Vec3::new(-slope, 0.0, 1.0).unit();
+        Color::new(0.5 + 0.5 * normal.x(), 0.5 + 0.5 * normal.y(), 0.5 + 0.5 * normal.z())
+    }).collect();
+    ImageTexture::new(n, n, pixels)
+}
+
+fn texture(filename: &str, load: fn(&str) -> Result<ImageTexture, image::ImageError>, fallback: ImageTexture) -> Arc<ImageTexture> {
+    Arc::new(load(filename).unwrap_or(fallback))
+}
+
+// Grid of spheres, one per material
+pub fn materials(ground: Arc<dyn Scatter>) -> World {
+    let white = Color::new(1.0, 1.0, 1.0);
+    let albedo = texture(ALBEDO_TEXTURE, ImageTexture::load, checker(8, Color::new(0.8, 0.8, 0.8), Color::new(0.2, 0.3, 0.6)));
+    let normals = texture(NORMAL_TEXTURE, ImageTexture::load_linear, ripples(256));
+    let mask = texture(MASK_TEXTURE, ImageTexture::load_alpha, checker(8, white, Color::new(0.0, 0.0, 0.0)));
+    let height = Arc::new(checker(16, white, Color::new(0.0, 0.0, 0.0)));
+
+    let red: Arc<dyn Scatter> = Arc::new(Lambertian::new(Color::new(0.7, 0.1, 0.1)));
+    let steel: Arc<dyn Scatter> = Arc::new(Metal::new(Color::new(0.7, 0.7, 0.75), 0.1));
+    let (aluminium_eta, aluminium_k) = Conductor::Aluminium.ior();
+
+    let mut materials: Vec<Arc<dyn Scatter>> = vec![
+        // Principled lobes
+        Arc::new(Principled{clearcoat: 1.0, roughness: 0.6, ..Principled::new(Color::new(0.8, 0.2, 0.1))}),
+        Arc::new(Principled{sheen: 1.0, roughness: 0.9, ..Principled::textured(Arc::new(albedo.tinted(Color::new(0.9, 0.6, 0.7))))}),
+        Arc::new(Principled{transmission: 1.0, roughness: 0.1, ..Principled::new(Color::new(0.6, 0.9, 0.7))}),
+        Arc::new(Principled{metallic: 1.0, roughness: 0.3, ..Principled::new(Color::new(0.9, 0.8, 0.6))}),
+        Arc::new(Principled{emission: Color::new(4.0, 2.0, 0.8), ..Principled::new(Color::new(0.0, 0.0, 0.0))}),
+        // Metals
+        Arc::new(MicrofacetConductor::from_color(Color::new(0.3, 0.8, 0.5), 0.3)),
+        Arc::new(MicrofacetConductor::new(aluminium_eta, aluminium_k, 0.05, 0.5).rotated(45.0)),
+        // Glasses
+        Arc::new(RoughDielectric::new(1.5, 0.3)),
+        Arc::new(ThinDielectric::new(1.5)),
+        Arc::new(Dielectric::with_ior(Ior::BK7)),
+        Arc::new(Dielectric::with_ior(Ior::SF11)),
+        Arc::new(Dielectric::colored(Ior::Cauchy{a: 1.5046, b: 0.0042}, Color::new(0.8, 0.3, 0.2), 0.5).prioritized(1)),
+        Arc::new(Subsurface::new(1.4, Color::new(0.3, 0.15, 0.08), Color::new(0.99, 0.95, 0.9))),
+        // Combinations
+        Arc::new(Mix::new(red.clone(), steel.clone(), 0.5)),
+        Arc::new(Mix::textured(red.clone(), Arc::new(Lambertian::textured(albedo.clone())), mask.clone())),
+        Arc::new(Layered::new(red.clone(), Ior::BK7.at(SODIUM_D), 0.05)),
+        Arc::new(Layered::tinted(steel.clone(), 1.5, 0.2, Color::new(0.9, 0.6, 0.3))),
+        Arc::new(NormalMapped::bump_map(red.clone(), height, 0.02)),
+        Arc::new(NormalMapped::normal_map(steel, normals)),
+        Arc::new(Cutout::new(red.clone(), 0.5)),
+        Arc::new(Cutout::textured(red, mask)),
+    ];
+    // Measured metals, looked up by name as a scene description would
+    for name in ["gold", "silver", "copper", "chrome", "iron", "titanium"] {
+        if let Some(conductor) = Conductor::from_name(name) {
+            materials.push(Arc::new(MicrofacetConductor::preset(conductor, 0.1)));
+        }
+    }
+
+    let mut world: World = vec![Box::new(Plane::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), ground))];
+    let columns = 6;
+    let rows = materials.len().div_ceil(columns);
+    for (i, mat) in materials.into_iter().enumerate() {
+        let x = (i % columns) as f64 - (columns - 1) as f64 / 2.0;
+        let z = (i / columns) as f64 - (rows - 1) as f64 / 2.0;
+        world.push(Box::new(Sphere::new(Vec3::new(x * 0.9, 0.35, z * 0.9), 0.35, mat)));
+    }
+    world
+}
+
+// Every primitive, in front of a terrain
+pub fn shapes(ground: Arc<dyn Scatter>) -> World {
+    let clay: Arc<dyn Scatter> = Arc::new(Lambertian::new(Color::new(0.8, 0.5, 0.3)));
+    let blue: Arc<dyn Scatter> = Arc::new(Lambertian::new(Color::new(0.2, 0.3, 0.7)));
+    let gold: Arc<dyn Scatter> = Arc::new(MicrofacetConductor::preset(Conductor::Gold, 0.2));
+    let glass: Arc<dyn Scatter> = Arc::new(Dielectric::new(1.5));
+    let grass: Arc<dyn Scatter> = Arc::new(Lambertian::new(Color::new(0.3, 0.5, 0.2)));
+    let albedo = texture(ALBEDO_TEXTURE, ImageTexture::load, checker(8, Color::new(0.8, 0.8, 0.8), Color::new(0.2, 0.3, 0.6)));
+
+    let terrain_origin = Vec3::new(-3.0, 0.0, -3.0);
+    let terrain_size = Vec3::new(6.0, 0.8, 1.5);
+    let terrain = Heightfield::from_image(TERRAIN_TEXTURE, terrain_origin, terrain_size, grass.clone()).unwrap_or_else(|_| {
+        let n = 64;
+        let heights = (0..n * n).map(|i| {
+            let (x, z) = ((i % n) as f64 / n as f64, (i / n) as f64 / n as f64);
+            0.5 + 0.25 * f64::sin(9.0 * x) * f64::cos(7.0 * z) + 0.25 * f64::sin(23.0 * x + 5.0 * z)
+        }).collect();
+        Heightfield::new(heights, n, n, terrain_origin, terrain_size, grass)
+    });
+
+    let y = Vec3::new(0.0, 1.0, 0.0);
+    let drilled = Csg::difference(Box::new(Cuboid::new(Vec3::new(-0.35, 0.0, 0.9), Vec3::new(0.35, 0.7, 1.6), clay.clone())),
+                                  Box::new(Cylinder::new(Vec3::new(0.0, -0.1, 1.25), y, 0.2, 0.9, true, clay.clone())));
+    let rounded = Csg::intersection(Box::new(Cuboid::new(Vec3::new(0.9, 0.0, 0.9), Vec3::new(1.5, 0.6, 1.5), glass.clone())),
+                                    Box::new(Sphere::new(Vec3::new(1.2, 0.3, 1.2), 0.4, glass)));
+    let blob = Csg::union(Box::new(Sphere::new(Vec3::new(-1.3, 0.3, 1.2), 0.3, blue.clone())),
+                          Box::new(Sphere::new(Vec3::new(-1.0, 0.45, 1.2), 0.25, blue.clone())));
+
+    // Box with a lattice of holes, a twisted bar and a fractal
+    let holes = Sdf::sphere(Vec3::new(0.0, 0.0, 0.0), 0.1).repeat(Vec3::new(0.3, 0.3, 0.3));
+    let sponge = Sdf::cube(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.3, 0.3, 0.3)).smooth_subtract(holes, 0.03)
+        .translate(Vec3::new(-1.2, 0.3, -1.2));
+    let twisted = Sdf::cube(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.15, 0.5, 0.15)).twist(1.5)
+        .smooth_union(Sdf::torus(Vec3::new(0.0, -0.4, 0.0), 0.3, 0.08), 0.1)
+        .smooth_union(Sdf::capsule(Vec3::new(-0.3, 0.55, 0.0), Vec3::new(0.3, 0.55, 0.0), 0.06), 0.05)
+        .translate(Vec3::new(0.0, 0.5, -1.2));
+
+    vec![
+        Box::new(Plane::new(Vec3::new(0.0, 0.0, 0.0), y, ground)),
+        Box::new(terrain),
+        Box::new(drilled),
+        Box::new(rounded),
+        Box::new(blob),
+        Box::new(Quad::new(Vec3::new(-1.6, 0.0, -0.3), Vec3::new(0.0, 0.0, 0.6), Vec3::new(0.0, 0.8, 0.0), gold.clone())),
+        Box::new(Disk::new(Vec3::new(-0.6, 0.01, 0.0), y, 0.3, blue.clone())),
+        Box::new(Cylinder::new(Vec3::new(0.0, 0.0, 0.0), y, 0.25, 0.6, true, gold.clone())),
+        Box::new(Cone::new(Vec3::new(0.7, 0.0, 0.0), y, 0.3, 0.7, true, clay.clone())),
+        Box::new(Torus::new(Vec3::new(1.5, 0.3, 0.0), Vec3::new(1.0, 1.0, 0.0), 0.25, 0.08, gold.clone())),
+        Box::new(Cuboid::new(Vec3::new(1.0, 0.0, -1.5), Vec3::new(1.6, 0.4, -0.9), Arc::new(Lambertian::textured(albedo)))),
+        Box::new(SdfObject::new(sponge, clay.clone())),
+        Box::new(SdfObject::new(twisted, gold)),
+        Box::new(SdfObject::new(Sdf::mandelbulb(Vec3::new(1.3, 1.2, -1.2), 0.45), clay)),
+    ]
+}
+
+// Meshes and glTF scene from files, along with the glTF camera if there is one
+pub fn imported(ground: Arc<dyn Scatter>, aspect_ratio: f64) -> (World, Option<Camera>) {
+    let mut world: World = vec![Box::new(Plane::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), ground))];
+    let mut camera = None;
+    let clay = Arc::new(Lambertian::new(Color::new(0.8, 0.5, 0.3)));
+
+    for (filename, mesh) in [(PLY_MODEL, ply::load(PLY_MODEL, clay.clone())),
+                             (STL_MODEL, stl::load(STL_MODEL, stl::StlOptions::default(), clay))] {
+        match mesh {
+            Ok(mesh) => {
+                let bounds = mesh.bounds();
+                println!("{}: {} triangles from {} to {}", filename, mesh.triangle_count(), bounds.min, bounds.max);
+                world.push(Box::new(mesh));
+            }
+            Err(e) => println!("Skipping {}: {}", filename, e),
+        }
+    }
+    match gltf::load(GLTF_SCENE, aspect_ratio) {
+        Ok(scene) => {
+            world.extend(scene.objects);
+            camera = scene.camera;
+        }
+        Err(e) => println!("Skipping {}: {}", GLTF_SCENE, e),
+    }
+    (world, camera)
+}
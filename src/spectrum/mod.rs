@@ -0,0 +1,200 @@
+use std::ops::{Add, Mul, Div};
+use std::sync::OnceLock;
+use crate::color::Color;
+
+// Spectral rendering support
+// Every path carries a few wavelengths, the first one being the hero: materials
+// which depend on the wavelength, like dispersive glass, only follow the hero
+// and the others are dropped from that path.
+// RGB reflectances and emissions are upsampled to smooth spectra, and radiance
+// goes back to RGB through the CIE 1931 color matching functions.
+
+pub const WAVELENGTHS: usize = 4;
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+// Values at each of a path's wavelengths
+#[derive(Debug, Copy, Clone)]
+pub struct Spectrum {
+    pub values: [f64; WAVELENGTHS],
+}
+
+impl Spectrum {
+    pub fn constant(v: f64) -> Self {
+        Spectrum{values: [v; WAVELENGTHS]}
+    }
+    pub fn max_component(&self) -> f64 {
+        self.values.iter().fold(0.0, |m, v| f64::max(m, *v))
+    }
+}
+
+impl Add for Spectrum {
+    type Output = Spectrum;
+    fn add(self, other: Spectrum) -> Spectrum {
+        Spectrum{values: std::array::from_fn(|i| self.values[i] + other.values[i])}
+    }
+}
+
+impl Mul for Spectrum {
+    type Output = Spectrum;
+    fn mul(self, other: Spectrum) -> Spectrum {
+        Spectrum{values: std::array::from_fn(|i| self.values[i] * other.values[i])}
+    }
+}
+
+impl Div<f64> for Spectrum {
+    type Output = Spectrum;
+    fn div(self, other: f64) -> Spectrum {
+        Spectrum{values: self.values.map(|v| v / other)}
+    }
+}
+
+// Wavelengths of a path in nanometers, and their sampling densities
+// A density of 0 marks a wavelength dropped from the path.
+#[derive(Debug, Copy, Clone)]
+pub struct Wavelengths {
+    pub lambda: [f64; WAVELENGTHS],
+    pub pdf: [f64; WAVELENGTHS],
+}
+
+impl Wavelengths {
+    // Stratified wavelengths, importance sampled where the eye is sensitive
+    // (pbrt's visible wavelengths distribution)
+    pub fn sample(u: f64) -> Self {
+        let lambda: [f64; WAVELENGTHS] = std::array::from_fn(|i| {
+            let u = (u + i as f64 / WAVELENGTHS as f64).fract();
+            538.0 - 138.888889 * f64::atanh(0.85691062 - 1.82750197 * u)
+        });
+        let pdf = lambda.map(|l| 0.0039398042 / f64::cosh(0.0072 * (l - 538.0)).powi(2));
+        Wavelengths{lambda, pdf}
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    // Keep only the hero, which then stands for all of them
+    pub fn terminate_secondary(&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        for pdf in self.pdf.iter_mut().skip(1) {
+            *pdf = 0.0;
+        }
+        self.pdf[0] /= WAVELENGTHS as f64;
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.pdf[1..].iter().all(|pdf| *pdf == 0.0)
+    }
+
+    // Smooth spectrum of a RGB color at our wavelengths
+    pub fn upsample(&self, c: Color) -> Spectrum {
+        Spectrum{values: self.lambda.map(|l| {
+            let [r, g, b] = bands(l);
+            c.r * r + c.g * g + c.b * b
+        })}
+    }
+
+    // Monte Carlo estimate of the RGB color of a radiance spectrum
+    pub fn rgb(&self, radiance: Spectrum) -> Color {
+        let mut sum = [0.0; 3];
+        for i in 0..WAVELENGTHS {
+            if self.pdf[i] == 0.0 {
+                continue;
+            }
+            let rgb = cmf_rgb(self.lambda[i]);
+            for c in 0..3 {
+                sum[c] += radiance.values[i] * rgb[c] / (self.pdf[i] * WAVELENGTHS as f64);
+            }
+        }
+        let m = band_to_rgb_inverse();
+        let rgb: [f64; 3] = std::array::from_fn(|c| m[c][0] * sum[0] + m[c][1] * sum[1] + m[c][2] * sum[2]);
+        Color::new(rgb[0], rgb[1], rgb[2])
+    }
+}
+
+// CIE 1931 color matching functions, multi-lobe fit of Wyman, Sloan and Shirley 2013
+pub fn cie_xyz(lambda: f64) -> [f64; 3] {
+    let g = |mu: f64, s1: f64, s2: f64| {
+        let t = (lambda - mu) / if lambda < mu { s1 } else { s2 };
+        f64::exp(-0.5 * t * t)
+    };
+    [1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+     0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+     1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8)]
+}
+
+// Color matching functions of the linear sRGB primaries
+fn cmf_rgb(lambda: f64) -> [f64; 3] {
+    let [x, y, z] = cie_xyz(lambda);
+    [ 3.2406 * x - 1.5372 * y - 0.4986 * z,
+     -0.9689 * x + 1.8758 * y + 0.0415 * z,
+      0.0557 * x - 0.2040 * y + 1.0570 * z]
+}
+
+fn smoothstep(a: f64, b: f64, x: f64) -> f64 {
+    let t = f64::clamp((x - a) / (b - a), 0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// Red, green and blue bands of the upsampling, they sum to 1 everywhere so
+// reflectances in [0,1] stay in [0,1] and white stays flat
+fn bands(lambda: f64) -> [f64; 3] {
+    let blue = 1.0 - smoothstep(470.0, 510.0, lambda);
+    let red = smoothstep(565.0, 605.0, lambda);
+    [red, 1.0 - red - blue, blue]
+}
+
+// The bands do not integrate back to pure primaries, the inverse of their
+// RGB colors corrects that so upsampled colors come back unchanged
+fn band_to_rgb_inverse() -> &'static [[f64; 3]; 3] {
+    static INVERSE: OnceLock<[[f64; 3]; 3]> = OnceLock::new();
+    INVERSE.get_or_init(|| {
+        let mut m = [[0.0; 3]; 3];
+        let mut lambda = LAMBDA_MIN;
+        while lambda <= LAMBDA_MAX {
+            let rgb = cmf_rgb(lambda);
+            let band = bands(lambda);
+            for (row, c) in m.iter_mut().zip(rgb) {
+                for (value, b) in row.iter_mut().zip(band) {
+                    *value += c * b;
+                }
+            }
+            lambda += 1.0;
+        }
+        invert(&m)
+    })
+}
+
+fn invert(m: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let cofactor = |r: usize, c: usize| {
+        let (r0, r1) = ((r + 1) % 3, (r + 2) % 3);
+        let (c0, c1) = ((c + 1) % 3, (c + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let det = m[0][0] * cofactor(0, 0) + m[0][1] * cofactor(0, 1) + m[0][2] * cofactor(0, 2);
+    std::array::from_fn(|r| std::array::from_fn(|c| cofactor(c, r) / det))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upsample_round_trip() {
+        // Averaged over stratified wavelengths the estimate converges to the color
+        let n = 4000;
+        for c in [Color::new(1.0, 1.0, 1.0), Color::new(1.0, 0.0, 0.0), Color::new(0.0, 1.0, 0.0), Color::new(0.0, 0.0, 1.0), Color::new(0.8, 0.3, 0.1)] {
+            let mut sum = Color::new(0.0, 0.0, 0.0);
+            for i in 0..n {
+                let wavelengths = Wavelengths::sample((i as f64 + 0.5) / n as f64);
+                sum = sum + wavelengths.rgb(wavelengths.upsample(c));
+            }
+            let rgb = sum / n as f64;
+            for (a, b) in [(rgb.r, c.r), (rgb.g, c.g), (rgb.b, c.b)] {
+                assert!((a - b).abs() < 0.01, "{:?} came back as {:?}", c, rgb);
+            }
+        }
+    }
+}
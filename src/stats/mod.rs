@@ -0,0 +1,110 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+// Render statistics, gathered per thread without any shared state.
+// Each worker accumulates into its thread local Stats, and hands them
+// over with take() once its job is done. The main thread merges them.
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    pub camera_rays: u64,
+    pub secondary_rays: u64,
+    pub intersection_tests: u64,
+    pub samples: u64,
+    pub hits: BTreeMap<&'static str, u64>,
+    pub path_lengths: Vec<u64>,
+}
+
+thread_local! {
+    static LOCAL: RefCell<Stats> = RefCell::new(Stats::default());
+}
+
+// Counters, called from the hot paths
+pub fn camera_ray() {
+    LOCAL.with(|s| s.borrow_mut().camera_rays += 1);
+}
+pub fn secondary_ray() {
+    LOCAL.with(|s| s.borrow_mut().secondary_rays += 1);
+}
+pub fn intersection_test() {
+    LOCAL.with(|s| s.borrow_mut().intersection_tests += 1);
+}
+pub fn sample() {
+    LOCAL.with(|s| s.borrow_mut().samples += 1);
+}
+pub fn hit(material: &'static str) {
+    LOCAL.with(|s| *s.borrow_mut().hits.entry(material).or_insert(0) += 1);
+}
+// Record the number of bounces of a terminated path
+pub fn path_length(bounces: usize) {
+    LOCAL.with(|s| {
+        let mut s = s.borrow_mut();
+        if s.path_lengths.len() <= bounces {
+            s.path_lengths.resize(bounces + 1, 0);
+        }
+        s.path_lengths[bounces] += 1;
+    });
+}
+
+// Get the statistics of the current thread, and reset them
+pub fn take() -> Stats {
+    LOCAL.with(|s| s.replace(Stats::default()))
+}
+
+impl Stats {
+    pub fn merge(&mut self, other: &Stats) {
+        self.camera_rays += other.camera_rays;
+        self.secondary_rays += other.secondary_rays;
+        self.intersection_tests += other.intersection_tests;
+        self.samples += other.samples;
+        for (name, count) in &other.hits {
+            *self.hits.entry(name).or_insert(0) += count;
+        }
+        if self.path_lengths.len() < other.path_lengths.len() {
+            self.path_lengths.resize(other.path_lengths.len(), 0);
+        }
+        for (i, count) in other.path_lengths.iter().enumerate() {
+            self.path_lengths[i] += count;
+        }
+    }
+
+    pub fn total_rays(&self) -> u64 {
+        self.camera_rays + self.secondary_rays
+    }
+
+    pub fn samples_per_second(&self, elapsed: Duration) -> f64 {
+        self.samples as f64 / elapsed.as_secs_f64()
+    }
+
+    pub fn print(&self, elapsed: Duration) {
+        println!("Ray count          : {}", self.total_rays());
+        println!("Camera rays        : {}", self.camera_rays);
+        println!("Secondary rays     : {}", self.secondary_rays);
+        println!("Intersection tests : {}", self.intersection_tests);
+        println!("Samples per second : {:.0}", self.samples_per_second(elapsed));
+        for (name, count) in &self.hits {
+            println!("Hits {:<14}: {}", name, count);
+        }
+    }
+
+    // Serialize as a JSON object
+    pub fn to_json(&self, width: u32, height: u32, elapsed: Duration) -> String {
+        let mut s = String::new();
+        writeln!(s, "{{").unwrap();
+        writeln!(s, "  \"width\": {},", width).unwrap();
+        writeln!(s, "  \"height\": {},", height).unwrap();
+        writeln!(s, "  \"seconds\": {},", elapsed.as_secs_f64()).unwrap();
+        writeln!(s, "  \"samples\": {},", self.samples).unwrap();
+        writeln!(s, "  \"samples_per_second\": {},", self.samples_per_second(elapsed)).unwrap();
+        writeln!(s, "  \"camera_rays\": {},", self.camera_rays).unwrap();
+        writeln!(s, "  \"secondary_rays\": {},", self.secondary_rays).unwrap();
+        writeln!(s, "  \"intersection_tests\": {},", self.intersection_tests).unwrap();
+        let hits: Vec<String> = self.hits.iter().map(|(name, count)| format!("\"{}\": {}", name, count)).collect();
+        writeln!(s, "  \"hits\": {{{}}},", hits.join(", ")).unwrap();
+        let lengths: Vec<String> = self.path_lengths.iter().map(|c| c.to_string()).collect();
+        writeln!(s, "  \"path_lengths\": [{}]", lengths.join(", ")).unwrap();
+        writeln!(s, "}}").unwrap();
+        s
+    }
+}
@@ -0,0 +1,92 @@
+use crate::vec3::Vec3;
+use crate::color::Color;
+
+// Spatially varying color, looked up from the surface coordinates of a hit
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, p: Vec3) -> Color;
+}
+
+
+#[derive(Debug, Copy, Clone)]
+pub struct SolidColor {
+    color: Color,
+}
+impl SolidColor {
+    pub fn new(color: Color) -> Self {
+        SolidColor{color}
+    }
+}
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: Vec3) -> Color {
+        self.color
+    }
+}
+
+
+// Image lookup, v goes up from the bottom row, UVs outside [0,1] wrap around
+// Pixels are stored as linear colors
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl ImageTexture {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert!(width > 0 && height > 0 && pixels.len() == width * height, "Texture size does not match its pixels");
+        ImageTexture{width, height, pixels}
+    }
+
+    // Load an 8 bits sRGB image
+    pub fn load(filename: &str) -> Result<Self, image::ImageError> {
+        let img = image::open(filename)?.into_rgb8();
+        let pixels = img.pixels().map(|p| Color::new(srgb_to_linear(p.0[0] as f64 / 255.0),
+                                                     srgb_to_linear(p.0[1] as f64 / 255.0),
+                                                     srgb_to_linear(p.0[2] as f64 / 255.0))).collect();
+        Ok(ImageTexture::new(img.width() as usize, img.height() as usize, pixels))
+    }
+
+    // Load an image holding data rather than colors, like normal or bump maps,
+    // without the sRGB decoding
+    pub fn load_linear(filename: &str) -> Result<Self, image::ImageError> {
+        let img = image::open(filename)?.into_rgb16();
+        let pixels = img.pixels().map(|p| Color::new(p.0[0] as f64 / 65535.0,
+                                                     p.0[1] as f64 / 65535.0,
+                                                     p.0[2] as f64 / 65535.0)).collect();
+        Ok(ImageTexture::new(img.width() as usize, img.height() as usize, pixels))
+    }
+
+    // Load the alpha channel of an image as a grayscale texture, opaque without one
+    pub fn load_alpha(filename: &str) -> Result<Self, image::ImageError> {
+        let img = image::open(filename)?.into_rgba16();
+        let pixels = img.pixels().map(|p| {
+            let a = p.0[3] as f64 / 65535.0;
+            Color::new(a, a, a)
+        }).collect();
+        Ok(ImageTexture::new(img.width() as usize, img.height() as usize, pixels))
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    // Copy with every pixel multiplied by a color
+    pub fn tinted(&self, tint: Color) -> ImageTexture {
+        ImageTexture::new(self.width, self.height, self.pixels.iter().map(|c| *c * tint).collect())
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: Vec3) -> Color {
+        let u = u - u.floor();
+        let v = 1.0 - (v - v.floor());
+        let x = usize::min((u * self.width as f64) as usize, self.width - 1);
+        let y = usize::min((v * self.height as f64) as usize, self.height - 1);
+        self.pixel(x, y)
+    }
+}
+
+// Decode a sRGB channel in [0,1]
+pub fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 { c / 12.92 } else { f64::powf((c + 0.055) / 1.055, 2.4) }
+}
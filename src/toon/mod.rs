@@ -0,0 +1,170 @@
+use crate::vec3::Vec3;
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::hittable::{Hittable, World};
+use crate::stats;
+
+// Non-photorealistic rendering, for diagrams
+// Surfaces get a few flat bands of diffuse light from a single key light and a
+// rim light on their silhouettes, then ink lines are drawn over the image where
+// the object, the depth or the normal change abruptly between pixels.
+
+const SHADOW_EPS: f64 = 0.01;
+
+#[derive(Debug, Copy, Clone)]
+pub struct Toon {
+    // Direction towards the key light
+    pub light: Vec3,
+    // Number of diffuse bands, unlit areas excluded
+    pub bands: u32,
+    // Light of the unlit band, fraction of the base color
+    pub ambient: f64,
+    // Rim light where the view grazes the surface, 1 - cosine above the threshold
+    pub rim_threshold: f64,
+    pub rim_strength: f64,
+    pub shadows: bool,
+    pub ink: Color,
+    // Relative curvature of the depth across a pixel above which it is an edge,
+    // zero on planes whatever their slope
+    pub depth_threshold: f64,
+    // Cosine between neighbouring normals below which it is a crease
+    pub normal_threshold: f64,
+}
+
+// What the outline pass needs to know about a pixel, from its center
+#[derive(Debug, Copy, Clone)]
+pub struct Fragment {
+    // World object, 0 for the background
+    pub object: usize,
+    pub depth: f64,
+    pub normal: Vec3,
+}
+
+impl Fragment {
+    pub fn background() -> Self {
+        Fragment{object: 0, depth: f64::INFINITY, normal: Vec3::new(0.0, 0.0, 0.0)}
+    }
+}
+
+impl Toon {
+    pub fn new(light: Vec3) -> Self {
+        Toon{
+            light: light.unit(),
+            bands: 3,
+            ambient: 0.3,
+            rim_threshold: 0.7,
+            rim_strength: 0.25,
+            shadows: true,
+            ink: Color::new(0.02, 0.02, 0.02),
+            depth_threshold: 0.02,
+            normal_threshold: 0.7,
+        }
+    }
+
+    // Cel shaded color seen along a ray, background from the sky function
+    // The base color of a surface is its material's, the albedo of diffuse and
+    // metallic materials and white for glass.
+    pub fn shade(&self, r: Ray, world: &World, sky: fn(Ray) -> Color) -> Color {
+        let Some(rec) = world.hit(r, SHADOW_EPS, f64::INFINITY) else {
+            return sky(r);
+        };
+        stats::hit(rec.mat.name());
+        let base = rec.mat.base_color(&rec);
+        let base = Color::new(f64::min(base.r, 1.0), f64::min(base.g, 1.0), f64::min(base.b, 1.0));
+
+        let mut diffuse = f64::max(rec.normal.dot(self.light), 0.0);
+        if diffuse > 0.0 && self.shadows {
+            stats::secondary_ray();
+            if world.hit(Ray::new(rec.p, self.light), SHADOW_EPS, f64::INFINITY).is_some() {
+                diffuse = 0.0;
+            }
+        }
+        let bands = self.bands.max(1) as f64;
+        let level = f64::ceil(diffuse * bands) / bands;
+
+        let mut color = base * (self.ambient + (1.0 - self.ambient) * level) + rec.mat.emitted(&rec);
+        let rim = 1.0 - f64::max(rec.normal.dot(-r.direction().unit()), 0.0);
+        if rim > self.rim_threshold && level > 0.0 {
+            color = color + Color::new(1.0, 1.0, 1.0) * self.rim_strength;
+        }
+        color
+    }
+
+    pub fn fragment(&self, r: Ray, world: &World) -> Fragment {
+        match world.hit(r, SHADOW_EPS, f64::INFINITY) {
+            Some(rec) => Fragment{object: rec.object, depth: rec.t * r.direction().length(), normal: rec.normal},
+            None => Fragment::background(),
+        }
+    }
+
+    fn edge(&self, a: &Fragment, b: &Fragment) -> bool {
+        a.object != b.object || (a.object != 0 && a.normal.dot(b.normal) < self.normal_threshold)
+    }
+
+    // Depth of a, between b and c on either side, is off the plane through them
+    // Inverse depth is linear in screen space on a plane.
+    fn crease(&self, a: &Fragment, b: &Fragment, c: &Fragment) -> bool {
+        if a.object == 0 || a.object != b.object || a.object != c.object {
+            return false;
+        }
+        let curvature = 1.0 / b.depth + 1.0 / c.depth - 2.0 / a.depth;
+        f64::abs(curvature) * a.depth > self.depth_threshold
+    }
+
+    // Draw ink lines over an image, fragments stored at the same offsets, rows
+    // of width pixels
+    pub fn outline(&self, image: &mut [Color], fragments: &[Fragment], width: usize) {
+        let height = fragments.len() / width;
+        let at = |x: usize, y: usize| &fragments[x + y * width];
+        for y in 0..height {
+            for x in 0..width {
+                let f = at(x, y);
+                let mut ink = (x + 1 < width && self.edge(f, at(x + 1, y)))
+                    || (y + 1 < height && self.edge(f, at(x, y + 1)));
+                if x > 0 && x + 1 < width {
+                    ink |= self.crease(f, at(x - 1, y), at(x + 1, y));
+                }
+                if y > 0 && y + 1 < height {
+                    ink |= self.crease(f, at(x, y - 1), at(x, y + 1));
+                }
+                if ink {
+                    image[x + y * width] = self.ink;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::hittable::Sphere;
+    use crate::material::{Metal, Dielectric};
+    use crate::material::microfacet::MicrofacetConductor;
+
+    fn black(_r: Ray) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    #[test]
+    fn deterministic_base_color() {
+        let toon = Toon{shadows: false, ..Toon::new(Vec3::new(0.0, 0.0, 1.0))};
+        let r = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let worlds: [(World, Color); 3] = [
+            (vec![Box::new(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Metal::new(Color::new(0.8, 0.6, 0.2), 1.0))))],
+             Color::new(0.8, 0.6, 0.2)),
+            (vec![Box::new(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Arc::new(MicrofacetConductor::from_color(Color::new(0.3, 0.8, 0.5), 0.9))))],
+             Color::new(0.3, 0.8, 0.5)),
+            (vec![Box::new(Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Dielectric::new(1.5))))],
+             Color::new(1.0, 1.0, 1.0)),
+        ];
+        for (world, expected) in &worlds {
+            // Fully lit, so the base color comes out as is, every time
+            for _ in 0..16 {
+                let c = toon.shade(r, world, black);
+                assert!((c.r - expected.r).abs() < 1e-6 && (c.g - expected.g).abs() < 1e-6 && (c.b - expected.b).abs() < 1e-6);
+            }
+        }
+    }
+}
@@ -17,7 +17,7 @@ pub struct Vec3 {
 impl Vec3 {
     // Constructor
     pub fn new(x: f64, y: f64, z: f64) -> Self {
-        Vec3{x: x, y: y, z: z}
+        Vec3{x, y, z}
     }
 
     // Accessors
@@ -50,7 +50,7 @@ impl Vec3 {
         *self/self.length()
     }
     pub fn random_mm(min: f64, max: f64) -> Vec3 {
-        let rng = fastrand::Rng::new();
+        let mut rng = fastrand::Rng::new();
         let rx = (rng.f64()*(max-min))-max;
         let ry = (rng.f64()*(max-min))-max;
         let rz = (rng.f64()*(max-min))-max;
@@ -72,14 +72,14 @@ impl Vec3 {
     pub fn random_in_hemisphere(normal: Vec3) -> Vec3 {
         let in_unit_sphere: Vec3 = Vec3::random_in_unit_sphere();
         if in_unit_sphere.dot(normal) > 0.0 {
-            return in_unit_sphere;
+            in_unit_sphere
         } else {
-            return -in_unit_sphere;
+            -in_unit_sphere
         }
     }
 
     pub fn random_in_unit_disk() -> Vec3 {
-        let rng = fastrand::Rng::new();
+        let mut rng = fastrand::Rng::new();
         loop {
             let p = Vec3::new((rng.f64()*(1.0-(-1.0)))-1.0, (rng.f64()*(1.0-(-1.0)))-1.0 , 0.0);
             if p.length_squared() >= 1.0 {
@@ -95,6 +95,13 @@ impl Vec3 {
     pub fn reflect(self, n: Vec3) -> Vec3 {
         self - 2.0 * self.dot(n) * n
     }
+    // Two unit vectors forming an orthonormal basis with the unit vector n
+    pub fn orthonormal_basis(n: Vec3) -> (Vec3, Vec3) {
+        let a = if n.x.abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let t = n.cross(a).unit();
+        let b = n.cross(t);
+        (t, b)
+    }
     pub fn refract(self, n: Vec3, etai_over_etat: f64) -> Vec3{
         let cos_theta: f64 =  f64::min(-self.dot(n), 1.0);
         let r_out_perp: Vec3 =  etai_over_etat * (self + cos_theta*n);
@@ -159,7 +166,7 @@ impl Mul<f64> for Vec3 {
 impl Mul<Vec3> for f64 {
     type Output = Vec3;
     fn mul(self, t: Vec3) -> Vec3 {
-        Vec3 {x: t.x * self as f64, y: t.y * self as f64, z: t.z * self as f64}
+        Vec3 {x: t.x * self, y: t.y * self, z: t.z * self}
     }
 }
 impl Div<i32> for Vec3 {